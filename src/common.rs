@@ -1,10 +1,97 @@
+use std::collections::HashMap;
 use std::convert::{Into, TryFrom, TryInto};
+#[cfg(feature = "network-capture")]
 use std::net::IpAddr;
 use std::ops::{Add, AddAssign};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fmt, num};
 
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::setting;
+
+// json numbers above 2^53 silently lose precision in javascript consumers,
+// so the large counter types optionally go out as quoted strings
+pub fn serialize_large_number<S: Serializer>(
+    value: u128,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let as_strings = setting::get_glob_conf()
+        .map(|conf| conf.read().unwrap().get_large_numbers_as_strings())
+        .unwrap_or(false);
+
+    if as_strings {
+        serializer.serialize_str(&value.to_string())
+    } else {
+        serializer.serialize_u128(value)
+    }
+}
+
+// per-signature state for the rate-limited error logger
+struct ErrorLogEntry {
+    window_start: Instant,
+    suppressed: u64,
+}
+
+lazy_static! {
+    static ref ERROR_LOG_STATE: Mutex<HashMap<String, ErrorLogEntry>> = Mutex::new(HashMap::new());
+}
+
+// log an error at most once per window per signature so a pervasive failure
+// (e.g. taskstats EPERM for every thread) can't flood the log at the
+// sampling rate; repeats inside the window are counted and reported when
+// the window rolls over
+pub fn log_error_limited(signature: &str, message: &str) {
+    let window = Duration::from_secs(
+        setting::get_glob_conf()
+            .map(|conf| conf.read().unwrap().get_error_log_window_secs())
+            .unwrap_or(None)
+            .unwrap_or(30),
+    );
+
+    let mut state = ERROR_LOG_STATE.lock().unwrap();
+    match state.get_mut(signature) {
+        Some(entry) if entry.window_start.elapsed() < window => {
+            entry.suppressed += 1;
+        }
+        Some(entry) => {
+            if entry.suppressed > 0 {
+                println!(
+                    "error: {} ({} repeats suppressed in the last {}s)",
+                    message,
+                    entry.suppressed,
+                    window.as_secs()
+                );
+            } else {
+                println!("error: {}", message);
+            }
+            entry.window_start = Instant::now();
+            entry.suppressed = 0;
+        }
+        None => {
+            println!("error: {}", message);
+            state.insert(
+                signature.to_string(),
+                ErrorLogEntry {
+                    window_start: Instant::now(),
+                    suppressed: 0,
+                },
+            );
+        }
+    }
+}
+
+// round to the nearest multiple of the granularity; unset or degenerate
+// granularities leave the value untouched
+fn quantize(value: u128, granularity: Option<u128>) -> u128 {
+    match granularity {
+        Some(granularity) if granularity > 1 => {
+            (value + granularity / 2) / granularity * granularity
+        }
+        _ => value,
+    }
+}
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
 pub struct Uid(u128);
@@ -62,6 +149,9 @@ impl Into<u32> for Gid {
 pub struct Inode(u128);
 
 impl Inode {
+    // only the capture path mints inodes from parsed /proc/net tables;
+    // everything else obtains them via TryFrom on fd link targets
+    #[cfg(feature = "network-capture")]
     pub fn new(inode: usize) -> Self {
         Self(inode.try_into().unwrap())
     }
@@ -75,7 +165,7 @@ impl TryFrom<&str> for Inode {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize)]
 pub struct Timestamp(u128);
 
 impl Timestamp {
@@ -91,12 +181,107 @@ impl Timestamp {
                 .as_nanos(),
         )
     }
+
+    // nanoseconds elapsed since an earlier timestamp, zero if it is newer
+    pub fn nanosecs_since(&self, earlier: &Timestamp) -> u128 {
+        self.0.saturating_sub(earlier.0)
+    }
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match timestamp_format() {
+            setting::TimestampFormat::Rfc3339 => {
+                serializer.serialize_str(&rfc3339_from_nanos(self.0))
+            }
+            setting::TimestampFormat::Unix => self.0.serialize(serializer),
+        }
+    }
+}
+
+fn timestamp_format() -> setting::TimestampFormat {
+    setting::get_glob_conf()
+        .map(|conf| conf.read().unwrap().get_timestamp_format())
+        .unwrap_or_default()
+}
+
+// rfc3339 rendering of an epoch-nanoseconds value
+fn rfc3339_from_nanos(nanos: u128) -> String {
+    let secs = (nanos / 1_000_000_000) as i64;
+    let subsec_nanos = (nanos % 1_000_000_000) as u32;
+
+    chrono::DateTime::from_timestamp(secs, subsec_nanos)
+        .map(|datetime| datetime.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true))
+        .unwrap_or_default()
+}
+
+// hex+ascii dump in the `hexdump -C` layout, capped at max_bytes with a
+// note about how much was cut; for raw netlink buffers in error output
+pub fn hexdump(buf: &[u8], max_bytes: usize) -> String {
+    let shown = &buf[..buf.len().min(max_bytes)];
+    let mut result = String::new();
+
+    for (line, chunk) in shown.chunks(16).enumerate() {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+
+        for (offset, byte) in chunk.iter().enumerate() {
+            // the classic extra gap between the two groups of eight
+            if offset == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+            ascii.push(if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            });
+        }
+
+        result.push_str(&format!("{:08x}  {:<49} |{}|\n", line * 16, hex, ascii));
+    }
+
+    if buf.len() > max_bytes {
+        result.push_str(&format!("... ({} more bytes)\n", buf.len() - max_bytes));
+    }
+
+    result
+}
+
+// serialize_with hooks for the plain epoch fields on TotalStat, so they
+// follow the timestamp_format config like Timestamp does
+pub fn serialize_unix_secs<S: Serializer>(secs: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    match timestamp_format() {
+        setting::TimestampFormat::Rfc3339 => {
+            serializer.serialize_str(&rfc3339_from_nanos(*secs as u128 * 1_000_000_000))
+        }
+        setting::TimestampFormat::Unix => secs.serialize(serializer),
+    }
+}
+
+pub fn serialize_unix_nanos<S: Serializer>(nanos: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    match timestamp_format() {
+        setting::TimestampFormat::Rfc3339 => {
+            serializer.serialize_str(&rfc3339_from_nanos(*nanos))
+        }
+        setting::TimestampFormat::Unix => nanos.serialize(serializer),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 // save nano seconds
 pub struct TimeCount(u128);
 
+impl Serialize for TimeCount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let granularity = setting::get_glob_conf()
+            .map(|conf| conf.read().unwrap().get_quantization().get_time_nanos())
+            .unwrap_or(None);
+
+        serialize_large_number(quantize(self.0, granularity), serializer)
+    }
+}
+
 impl TimeCount {
     const NANOSECONDS_PER_SECOND: usize = 1_000_000_000;
     const NANOSECONDS_PER_MILLISECOND: usize = 1_000_000;
@@ -125,6 +310,21 @@ impl TimeCount {
     pub fn from_nanosecs(nanosecs: usize) -> Self {
         Self(nanosecs.try_into().unwrap())
     }
+    pub fn as_nanosecs(&self) -> u128 {
+        self.0
+    }
+    // None means underflow, i.e. the counter was reset between samples
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+    // average time per event, zero when nothing was counted
+    pub fn avg_per(self, count: Count) -> Self {
+        if count.0 == 0 {
+            Self(0)
+        } else {
+            Self(self.0 / count.0)
+        }
+    }
 }
 
 impl Add<Self> for TimeCount {
@@ -141,10 +341,20 @@ impl AddAssign<Self> for TimeCount {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 // save bytes
 pub struct DataCount(u128);
 
+impl Serialize for DataCount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let granularity = setting::get_glob_conf()
+            .map(|conf| conf.read().unwrap().get_quantization().get_data_bytes())
+            .unwrap_or(None);
+
+        serialize_large_number(quantize(self.0, granularity), serializer)
+    }
+}
+
 impl DataCount {
     pub fn from_byte(byte: usize) -> Self {
         Self(byte as u128)
@@ -167,6 +377,19 @@ impl DataCount {
     pub fn from_eb(eb: usize) -> Self {
         Self(eb as u128 * 1024 * 1024 * 1024 * 1024 * 1024 * 1024)
     }
+    pub fn as_bytes(&self) -> u128 {
+        self.0
+    }
+    // None means underflow, i.e. the counter was reset between samples
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+}
+
+impl fmt::Display for DataCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 impl Add<Self> for DataCount {
@@ -183,13 +406,23 @@ impl AddAssign<Self> for DataCount {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Count(u128);
 
+impl Serialize for Count {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_large_number(self.0, serializer)
+    }
+}
+
 impl Count {
     pub fn new(count: usize) -> Self {
         Self(count as u128)
     }
+    // None means underflow, i.e. the counter was reset between samples
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
 }
 
 impl Add<Self> for Count {
@@ -206,6 +439,7 @@ impl AddAssign<Self> for Count {
     }
 }
 
+#[cfg(feature = "network-capture")]
 pub enum Endian {
     Little,
     Big,
@@ -220,6 +454,7 @@ pub fn next_align_num(curr_num: usize, align: usize) -> usize {
     ((curr_num + align - 1) / align) * align
 }
 
+#[cfg(feature = "network-capture")]
 pub fn parse_hex_str(input: &str, endian: Endian) -> Result<Vec<u8>, CommonError> {
     if input.len() % 2 != 0 {
         return Err(CommonError::OddLenHexStr(input.len()));
@@ -271,6 +506,7 @@ impl From<num::ParseIntError> for CommonError {
     }
 }
 
+#[cfg(feature = "network-capture")]
 pub fn addr_in_network(addr: &IpAddr, net_addr: &IpAddr, net_mask: &IpAddr) -> Result<bool, ()> {
     // check if they are same kind of address
     
@@ -308,3 +544,106 @@ pub fn addr_in_network(addr: &IpAddr, net_addr: &IpAddr, net_mask: &IpAddr) -> R
         _ => Err(()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_counters_round_trip_as_strings() {
+        // the shared test config turns large_numbers_as_strings on
+        setting::install_test_config();
+
+        // 2^53 + 1 is the first integer javascript consumers would corrupt
+        let value: u128 = 9007199254740993;
+        let serialized = serde_json::to_string(&Count::new(value as usize)).unwrap();
+        assert_eq!(serialized, format!("\"{}\"", value));
+
+        // the quoted digits parse back to exactly the original value
+        let parsed: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(parsed.as_str().unwrap().parse::<u128>().unwrap(), value);
+    }
+
+    #[test]
+    fn quantize_rounds_to_the_nearest_granule() {
+        // kib quantization rounds half up
+        assert_eq!(quantize(1536, Some(1024)), 2048);
+        assert_eq!(quantize(1535, Some(1024)), 1024);
+        assert_eq!(quantize(100, Some(1024)), 0);
+
+        // unset or degenerate granularities leave the value alone
+        assert_eq!(quantize(1536, None), 1536);
+        assert_eq!(quantize(1536, Some(1)), 1536);
+    }
+
+    #[test]
+    fn repeated_errors_inside_the_window_are_suppressed() {
+        // a signature no other caller uses, the logger state is process-wide
+        let signature = "test-log-error-limited";
+
+        log_error_limited(signature, "boom");
+        log_error_limited(signature, "boom");
+        log_error_limited(signature, "boom");
+
+        // only the first call logged, the repeats were counted for the
+        // aggregated line at the next window rollover
+        let state = ERROR_LOG_STATE.lock().unwrap();
+        assert_eq!(state.get(signature).unwrap().suppressed, 2);
+    }
+
+    #[test]
+    fn kib_quantization_applies_during_serialization() {
+        // the shared test config rounds data counts to whole kib (and emits
+        // large numbers as strings)
+        setting::install_test_config();
+
+        let serialized = serde_json::to_string(&DataCount::from_byte(1536)).unwrap();
+        assert_eq!(serialized, "\"2048\"");
+    }
+
+    #[test]
+    fn nanosecs_since_subtracts_and_saturates() {
+        assert_eq!(Timestamp(10).nanosecs_since(&Timestamp(4)), 6);
+        // a newer "earlier" timestamp saturates to zero instead of wrapping
+        assert_eq!(Timestamp(4).nanosecs_since(&Timestamp(10)), 0);
+    }
+
+    #[test]
+    fn rfc3339_renders_epoch_nanos() {
+        assert_eq!(rfc3339_from_nanos(0), "1970-01-01T00:00:00.000000000Z");
+        assert_eq!(
+            rfc3339_from_nanos(1_500_000_000_000_000_005),
+            "2017-07-14T02:40:00.000000005Z"
+        );
+    }
+
+    #[test]
+    fn next_align_num_rounds_up_to_multiples() {
+        assert_eq!(next_align_num(5, 4), 8);
+        assert_eq!(next_align_num(8, 4), 8);
+        assert_eq!(next_align_num(0, 4), 0);
+    }
+
+    #[cfg(feature = "network-capture")]
+    #[test]
+    fn parse_hex_str_honors_endianness() {
+        assert_eq!(parse_hex_str("0102", Endian::Big).unwrap(), vec![1, 2]);
+        assert_eq!(parse_hex_str("0102", Endian::Little).unwrap(), vec![2, 1]);
+        assert!(matches!(
+            parse_hex_str("abc", Endian::Big),
+            Err(CommonError::OddLenHexStr(3))
+        ));
+    }
+
+    #[cfg(feature = "network-capture")]
+    #[test]
+    fn addr_in_network_masks_v4() {
+        let network: IpAddr = "192.168.1.0".parse().unwrap();
+        let mask: IpAddr = "255.255.255.0".parse().unwrap();
+
+        let inside: IpAddr = "192.168.1.5".parse().unwrap();
+        let outside: IpAddr = "192.168.2.5".parse().unwrap();
+        assert_eq!(addr_in_network(&inside, &network, &mask), Ok(true));
+        assert_eq!(addr_in_network(&outside, &network, &mask), Ok(false));
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// bounded best-effort enrichment executor, e.g. for reverse-dns lookups on
+// connection remotes. a fixed pool of worker threads drains a bounded queue,
+// so a burst of slow lookups can never exhaust fds or threads and core
+// collection never waits on one. results attach opportunistically: a sample
+// takes whatever is ready and skips the rest
+pub struct Enricher<K, V> {
+    // bounded job queue feeding the workers; a full queue drops the request
+    // instead of blocking the caller, it simply retries on a later sample
+    jobs: SyncSender<Job<K, V>>,
+
+    // keys queued or running, so the same lookup isn't enqueued twice
+    pending: Arc<Mutex<HashSet<K>>>,
+
+    results: Arc<Mutex<HashMap<K, V>>>,
+}
+
+type Job<K, V> = (K, Box<dyn FnOnce() -> Option<V> + Send>);
+
+impl<K, V> Enricher<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Send + 'static,
+{
+    pub fn new(max_in_flight: usize, lookup_timeout: Duration) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+        let (jobs, job_receiver) = mpsc::sync_channel::<Job<K, V>>(max_in_flight);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        let results = Arc::new(Mutex::new(HashMap::new()));
+
+        // at most max_in_flight lookups run concurrently because exactly
+        // that many workers exist; they park on the queue when idle
+        for _ in 0..max_in_flight {
+            let job_receiver = Arc::clone(&job_receiver);
+            let pending = Arc::clone(&pending);
+            let results = Arc::clone(&results);
+            thread::spawn(move || {
+                Self::worker(job_receiver, pending, results, lookup_timeout)
+            });
+        }
+
+        Self {
+            jobs,
+            pending,
+            results,
+        }
+    }
+
+    fn worker(
+        job_receiver: Arc<Mutex<Receiver<Job<K, V>>>>,
+        pending: Arc<Mutex<HashSet<K>>>,
+        results: Arc<Mutex<HashMap<K, V>>>,
+        lookup_timeout: Duration,
+    ) {
+        loop {
+            // hold the receiver lock only while taking a job, not while
+            // running it; exits when the enricher is dropped
+            let (key, lookup) = match job_receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+
+            let started = Instant::now();
+            let value = lookup();
+
+            pending.lock().unwrap().remove(&key);
+
+            // a lookup that overran its timeout is stale by definition;
+            // drop the late result instead of attaching it
+            if started.elapsed() > lookup_timeout {
+                continue;
+            }
+            if let Some(value) = value {
+                results.lock().unwrap().insert(key, value);
+            }
+        }
+    }
+
+    // queue a lookup for this key unless one is already cached, queued or
+    // running, or the queue is full; never blocks the caller
+    pub fn request<F>(&self, key: K, lookup: F)
+    where
+        F: FnOnce() -> Option<V> + Send + 'static,
+    {
+        if self.results.lock().unwrap().contains_key(&key) {
+            return;
+        }
+
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.contains(&key) {
+                return;
+            }
+            pending.insert(key.clone());
+        }
+
+        if let Err(TrySendError::Full((key, _)) | TrySendError::Disconnected((key, _))) =
+            self.jobs.try_send((key, Box::new(lookup)))
+        {
+            self.pending.lock().unwrap().remove(&key);
+        }
+    }
+
+    // whatever is ready now; lookups still running attach on a later sample
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.results.lock().unwrap().get(key).cloned()
+    }
+
+    #[allow(unused)]
+    pub fn pending_count(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    // drop cached results, e.g. after a config update changed what the
+    // lookups would return
+    #[allow(unused)]
+    pub fn clear(&self) {
+        self.results.lock().unwrap().clear();
+    }
+}
+
+// reverse dns through the system resolver; ipv4-mapped addresses and
+// failures both come back as None so callers just skip the attachment
+pub fn reverse_dns(addr: IpAddr) -> Option<String> {
+    let mut host = [0u8; libc::NI_MAXHOST as usize];
+
+    let result = match addr {
+        IpAddr::V4(v4) => {
+            let sockaddr = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: 0,
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(v4.octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe {
+                libc::getnameinfo(
+                    &sockaddr as *const libc::sockaddr_in as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    host.as_mut_ptr() as *mut libc::c_char,
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+        IpAddr::V6(v6) => {
+            let sockaddr = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: 0,
+                sin6_flowinfo: 0,
+                sin6_addr: libc::in6_addr {
+                    s6_addr: v6.octets(),
+                },
+                sin6_scope_id: 0,
+            };
+            unsafe {
+                libc::getnameinfo(
+                    &sockaddr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                    host.as_mut_ptr() as *mut libc::c_char,
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+    };
+
+    if result != 0 {
+        return None;
+    }
+
+    let end = host.iter().position(|&byte| byte == 0)?;
+    String::from_utf8(host[..end].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn concurrency_never_exceeds_the_worker_count() {
+        let enricher: Enricher<u32, u32> = Enricher::new(2, Duration::from_secs(5));
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        for key in 0..16u32 {
+            let running = Arc::clone(&running);
+            let peak = Arc::clone(&peak);
+            enricher.request(key, move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                Some(key)
+            });
+        }
+
+        // long enough for every accepted lookup to have drained
+        thread::sleep(Duration::from_millis(500));
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn results_attach_once_ready_and_cache() {
+        let enricher: Enricher<&str, String> = Enricher::new(1, Duration::from_secs(5));
+
+        assert_eq!(enricher.get(&"key"), None);
+        enricher.request("key", || Some(String::from("value")));
+
+        // poll instead of sleeping a fixed time, lookups are asynchronous
+        let mut result = None;
+        for _ in 0..100 {
+            result = enricher.get(&"key");
+            if result.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(result, Some(String::from("value")));
+
+        // a cached key is never looked up again
+        enricher.request("key", || panic!("cached key was re-resolved"));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(enricher.get(&"key"), Some(String::from("value")));
+    }
+
+    #[test]
+    fn overran_lookups_are_discarded() {
+        let enricher: Enricher<u8, u8> = Enricher::new(1, Duration::from_millis(5));
+
+        enricher.request(1, || {
+            thread::sleep(Duration::from_millis(50));
+            Some(1)
+        });
+
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(enricher.get(&1), None);
+        // the slot freed up even though the result was dropped
+        assert_eq!(enricher.pending_count(), 0);
+    }
+}
@@ -1,36 +1,136 @@
 mod common;
+mod enrichment;
+#[cfg(target_os = "linux")]
 mod netlink;
 mod network_stat;
 mod process;
+mod retry;
 mod setting;
+#[cfg(target_os = "linux")]
+mod taskstat;
+#[cfg(not(target_os = "linux"))]
+#[path = "taskstat_stub.rs"]
 mod taskstat;
 use kafka::producer::{Producer, Record, RequiredAcks};
 use serde::Serialize;
 use setting::update_glob_conf;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::{task, time};
 
+use base64::engine::general_purpose;
+use base64::Engine;
 use dotenv::dotenv;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::StreamExt;
+use redis::AsyncCommands;
+use regex::Regex;
 use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::net::IpAddr;
 use std::process::Command;
 use std::{env, fmt, io};
 
 #[macro_use]
 extern crate lazy_static;
 
-use process::iterate_proc_tree;
+use process::{iterate_proc_tree, iterate_proc_tree_roots_only};
+use setting::TreeMode;
 
+use crate::common::{log_error_limited, DataCount, TimeCount};
 use crate::network_stat::{NetworkRawStat, NetworkStatError};
 use crate::process::{Pid, ProcessError};
 use crate::setting::ConfigError;
 use crate::taskstat::{TaskStatsConnection, TaskStatsError};
 
+// capture-vs-attribution quality signals for one container (or the whole
+// sample), turning silent collection gaps into measurable numbers
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Coverage {
+    // pids the target resolved to, and how many of them were actually read
+    pids_requested: usize,
+    pids_collected: usize,
+
+    // socket inodes held by the collected processes, and how many of them
+    // matched a connection entry in the network raw stat
+    sockets_seen: usize,
+    sockets_attributed: usize,
+}
+
+impl Coverage {
+    fn for_processes(real_pid_list: &[Pid], processes: &[process::Process]) -> Self {
+        let pids_collected = real_pid_list
+            .iter()
+            .filter(|real_pid| {
+                processes
+                    .iter()
+                    .any(|proc| proc.get_real_pid() == **real_pid)
+            })
+            .count();
+
+        let mut sockets_seen = 0;
+        let mut sockets_attributed = 0;
+        for proc in processes {
+            sockets_seen += proc.get_socket_count();
+            sockets_attributed += proc.get_attributed_socket_count();
+        }
+
+        Self {
+            pids_requested: real_pid_list.len(),
+            pids_collected,
+            sockets_seen,
+            sockets_attributed,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Coverage {
+    fn add_assign(&mut self, other: Self) {
+        self.pids_requested += other.pids_requested;
+        self.pids_collected += other.pids_collected;
+        self.sockets_seen += other.sockets_seen;
+        self.sockets_attributed += other.sockets_attributed;
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct ContainerStat {
     container_name: String,
     processes: Vec<process::Process>,
+
+    // summed process stats, only set on the synthetic "_host" entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aggregated_stat: Option<process::ProcessStat>,
+
+    coverage: Coverage,
+
+    // the max_pids cap dropped part of this container's pid list
+    pids_truncated: bool,
+
+    // filesystem usage of the container's root mount, only collected when
+    // collect_disk_usage is on and the mount was readable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_total: Option<DataCount>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_used: Option<DataCount>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_avail: Option<DataCount>,
+}
+
+// one record per process, used by the "flat" output shape
+#[derive(Serialize)]
+pub struct FlatProcessRecord<'a> {
+    container_name: &'a str,
+    unix_timestamp: u64, // in seconds
+    process: &'a process::Process,
 }
 
 #[derive(Serialize)]
@@ -38,14 +138,280 @@ pub struct MessageChunk {
     sensor_name: String,
     cluster_name: String,
     message: String,
+
+    // codec the message was compressed with, so consumers can decompress
+    compression: setting::PayloadCompression,
 }
 
 impl MessageChunk {
-    pub fn new(sensor_name: String, cluster_name: String, message: String) -> Self {
+    pub fn new(
+        sensor_name: String,
+        cluster_name: String,
+        message: String,
+        compression: setting::PayloadCompression,
+    ) -> Self {
         Self {
             sensor_name,
             cluster_name,
             message,
+            compression,
+        }
+    }
+}
+
+// pluggable transport for publishing message chunks, keeping the sampling
+// loop free of transport details. publishing blocks, like the clients it wraps
+trait OutputSink: Send {
+    // called once before each sample's chunks are published
+    fn begin_sample(&mut self) {}
+
+    fn publish(&mut self, chunk: &MessageChunk) -> Result<(), SinkError>;
+
+    // called once at shutdown so nothing published stays buffered; sinks
+    // that publish synchronously have nothing to do
+    fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+struct KafkaSink {
+    producer: Producer,
+}
+
+impl KafkaSink {
+    fn new(producer: Producer) -> Self {
+        Self { producer }
+    }
+}
+
+impl OutputSink for KafkaSink {
+    fn publish(&mut self, chunk: &MessageChunk) -> Result<(), SinkError> {
+        let kafka_retry = setting::get_glob_conf()?.read().unwrap().get_kafka_retry();
+
+        let topic = format!("monitoring");
+        let record = Record::from_value(&topic, serde_json::to_string(chunk).unwrap());
+        retry::retry_blocking(&kafka_retry, || self.producer.send(&record))?;
+
+        println!("Sent to kafka !");
+        Ok(())
+    }
+}
+
+// the dev-mode sink, one chunk_<n>.json[.gz] file per chunk of the sample
+struct FileSink {
+    next_chunk: usize,
+
+    // files written since the last flush, kept open so flush can fsync them
+    pending_sync: Vec<fs::File>,
+}
+
+impl FileSink {
+    fn new() -> Self {
+        let _ = fs::create_dir_all("./results");
+        Self {
+            next_chunk: 0,
+            pending_sync: Vec::new(),
+        }
+    }
+}
+
+impl OutputSink for FileSink {
+    // chunk numbering restarts every sample so a sample overwrites the last one
+    fn begin_sample(&mut self) {
+        self.next_chunk = 0;
+        self.pending_sync.clear();
+    }
+
+    fn publish(&mut self, chunk: &MessageChunk) -> Result<(), SinkError> {
+        let dev_compress = setting::get_glob_conf()?.read().unwrap().get_dev_compress();
+        let chunk_json = serde_json::to_string(chunk).unwrap();
+
+        if dev_compress {
+            // keep more history on disk by gzipping each sample file
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            let _ = encoder.write_all(chunk_json.as_bytes());
+            let mut file =
+                fs::File::create(format!("./results/chunk_{}.json.gz", self.next_chunk))?;
+            file.write_all(&encoder.finish().unwrap())?;
+            self.pending_sync.push(file);
+            println!("Wrote to results/chunk_{}.json.gz", self.next_chunk);
+        } else {
+            let mut file = fs::File::create(format!("./results/chunk_{}.json", self.next_chunk))?;
+            file.write_all(chunk_json.as_bytes())?;
+            self.pending_sync.push(file);
+            println!("Wrote to results/chunk_{}.json", self.next_chunk);
+        }
+
+        self.next_chunk += 1;
+        Ok(())
+    }
+
+    // fsync the last sample's files so a shutdown doesn't lose them to the
+    // page cache
+    fn flush(&mut self) -> Result<(), SinkError> {
+        for file in self.pending_sync.drain(..) {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+// one json chunk per line on stdout, handy for piping into jq
+struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn publish(&mut self, chunk: &MessageChunk) -> Result<(), SinkError> {
+        println!("{}", serde_json::to_string(chunk).unwrap());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        io::stdout().flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum SinkError {
+    KafkaErr(kafka::Error),
+    IOErr(io::Error),
+    ConfigErr(ConfigError),
+}
+
+impl std::error::Error for SinkError {}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self {
+            Self::KafkaErr(error) => String::from(format!("Kafka error: {}", error)),
+            Self::IOErr(error) => String::from(format!("IO error: {}", error)),
+            Self::ConfigErr(error) => String::from(format!("Config error: {}", error)),
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+impl From<kafka::Error> for SinkError {
+    fn from(error: kafka::Error) -> Self {
+        Self::KafkaErr(error)
+    }
+}
+
+impl From<io::Error> for SinkError {
+    fn from(error: io::Error) -> Self {
+        Self::IOErr(error)
+    }
+}
+
+impl From<ConfigError> for SinkError {
+    fn from(error: ConfigError) -> Self {
+        Self::ConfigErr(error)
+    }
+}
+
+// escape a tag value per the influx line-protocol rules; commas, equals
+// signs and spaces would otherwise break the line apart
+fn escape_line_protocol_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+// one line-protocol measurement per process, tagged so influx/telegraf can
+// group by container and command; the timestamp is the sample's nanoseconds
+fn process_to_line_protocol(
+    sensor_name: &str,
+    container_name: &str,
+    proc: &process::Process,
+    timestamp_nanos: u128,
+) -> String {
+    let stat = proc.get_stat();
+
+    format!(
+        "process_stat,sensor={},container={},pid={},command={} cpu_time_ns={}i,rss_bytes={}i,io_read_bytes={}i,io_write_bytes={}i {}",
+        escape_line_protocol_tag(sensor_name),
+        escape_line_protocol_tag(container_name),
+        proc.get_real_pid(),
+        escape_line_protocol_tag(proc.get_command().trim()),
+        stat.get_total_cpu_time().as_nanosecs(),
+        stat.get_total_rss().as_bytes(),
+        stat.get_total_io_read().as_bytes(),
+        stat.get_total_io_write().as_bytes(),
+        timestamp_nanos,
+    )
+}
+
+// the summed stats of every target, emitted as the synthetic "_host" entry
+fn host_aggregate_stat(container_stats: &[ContainerStat]) -> process::ProcessStat {
+    let mut host_stat = process::ProcessStat::new();
+
+    for container_stat in container_stats {
+        for proc in &container_stat.processes {
+            host_stat += proc.get_stat().clone();
+        }
+
+        // in aggregate detail mode the per-process objects are gone and
+        // the container sum is all there is to fold in
+        if let Some(aggregated_stat) = &container_stat.aggregated_stat {
+            host_stat += aggregated_stat.clone();
+        }
+    }
+
+    host_stat
+}
+
+// one record per process across all containers, each carrying enough
+// context (container, timestamp) to stand alone in a stream pipeline
+fn flat_records(
+    total_stat: &TotalStat,
+    payload_compression: setting::PayloadCompression,
+) -> Vec<String> {
+    let mut records = Vec::new();
+    for container_stat in &total_stat.container_stats {
+        for proc in &container_stat.processes {
+            let record = FlatProcessRecord {
+                container_name: &container_stat.container_name,
+                unix_timestamp: total_stat.unix_timestamp,
+                process: proc,
+            };
+            records.push(compress_payload(
+                serde_json::to_string(&record).unwrap(),
+                payload_compression,
+            ));
+        }
+    }
+    records
+}
+
+// split a serialized sample into fixed-size chunks; without a cap the whole
+// payload goes out as one chunk
+fn chunk_payload(payload: String, chunk_size: Option<usize>) -> Vec<String> {
+    match chunk_size {
+        Some(size) => payload
+            .chars()
+            .collect::<Vec<char>>()
+            .chunks(size)
+            .map(|c| c.iter().collect::<String>())
+            .collect(),
+        None => vec![payload; 1],
+    }
+}
+
+// compressed payloads are base64 encoded so the chunks stay valid json strings
+fn compress_payload(payload: String, compression: setting::PayloadCompression) -> String {
+    match compression {
+        setting::PayloadCompression::None => payload,
+        setting::PayloadCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload.as_bytes()).unwrap();
+            general_purpose::STANDARD.encode(encoder.finish().unwrap())
+        }
+        setting::PayloadCompression::Zstd => {
+            let compressed = zstd::encode_all(payload.as_bytes(), 0).unwrap();
+            general_purpose::STANDARD.encode(compressed)
         }
     }
 }
@@ -55,38 +421,322 @@ impl ContainerStat {
         Self {
             container_name,
             processes: Vec::new(),
+            aggregated_stat: None,
+            coverage: Coverage::default(),
+            pids_truncated: false,
+            disk_total: None,
+            disk_used: None,
+            disk_avail: None,
         }
     }
 }
 
+// a collection failure tied to the target (and pid) it affected, reported in
+// the sample itself so consumers can tell "no processes" from "couldn't read"
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionError {
+    container_name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    real_pid: Option<Pid>,
+
+    error: String,
+}
+
+impl CollectionError {
+    fn new(container_name: &str, real_pid: Option<Pid>, error: String) -> Self {
+        Self {
+            container_name: container_name.to_string(),
+            real_pid,
+            error,
+        }
+    }
+}
+
+// the final record for a process present last sample but gone now; emitted
+// exactly once because the previous-sample table is replaced every tick
+#[derive(Debug, Clone, Serialize)]
+pub struct Tombstone {
+    process_uid: String,
+    terminated: bool,
+
+    // only present when an exit code was captured; taskstats can't report
+    // one for a process that already vanished from /proc
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<usize>,
+
+    last_total_cpu_time: TimeCount,
+}
+
+// one tombstone per identity seen last sample but missing from this one
+fn tombstones_for(
+    prev_process_stats: &HashMap<String, process::ProcessStat>,
+    curr_process_stats: &HashMap<String, process::ProcessStat>,
+) -> Vec<Tombstone> {
+    let mut tombstones = Vec::new();
+    for (process_uid, prev_stat) in prev_process_stats {
+        if !curr_process_stats.contains_key(process_uid) {
+            tombstones.push(Tombstone {
+                process_uid: process_uid.clone(),
+                terminated: true,
+                exit_code: None,
+                last_total_cpu_time: prev_stat.get_total_cpu_time(),
+            });
+        }
+    }
+    tombstones
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct TotalStat {
     container_stats: Vec<ContainerStat>,
     network_rawstat: NetworkRawStat,
 
-    #[serde(skip_serializing_if = "setting::has_unix_timestamp")]
+    // partial failures recorded during this sample's collection
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<CollectionError>,
+
+    // the per-container coverages summed over the whole sample
+    coverage: Coverage,
+
+    // tombstones for processes that disappeared since the previous sample
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tombstones: Vec<Tombstone>,
+
+    #[serde(
+        skip_serializing_if = "setting::has_unix_timestamp",
+        serialize_with = "common::serialize_unix_secs"
+    )]
     unix_timestamp: u64, // in seconds
+
+    // full-precision timestamp so consumers can order sub-second samples;
+    // the seconds field above stays for compatibility
+    #[serde(
+        skip_serializing_if = "setting::has_unix_nanos",
+        serialize_with = "common::serialize_unix_nanos"
+    )]
+    unix_nanos: u128,
+
+    // how far the previous collection overran the publish interval
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drift_ms: Option<u64>,
+
+    // host identity for provenance; the boot id changes on reboot, which
+    // also resets the pid namespace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    boot_id: Option<String>,
+
+    // which taskstats struct version the kernel produced, known after the
+    // first successful parse; parsing branches on versions 8 through 11
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taskstats_version: Option<u16>,
+
+    // field semantics (cumulative vs gauge, units) for consumers that
+    // can't hardcode them; opt-in since it repeats on every sample
+    #[serde(skip_serializing_if = "Option::is_none")]
+    semantics: Option<serde_json::Value>,
+}
+
+lazy_static! {
+    // read once at startup; both values are stable for the lifetime of
+    // a boot, so there is no point re-reading them every sample
+    static ref HOSTNAME: Option<String> = fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|content| content.trim().to_string());
+    static ref BOOT_ID: Option<String> = fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|content| content.trim().to_string());
+}
+
+impl fmt::Display for TotalStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary())
+    }
 }
 
 impl TotalStat {
+    // one-line overview for logs: what this sample covers and how much io
+    // its processes have accumulated
+    pub fn summary(&self) -> String {
+        let process_count: usize = self
+            .container_stats
+            .iter()
+            .map(|container_stat| container_stat.processes.len())
+            .sum();
+
+        let mut total_io = DataCount::from_byte(0);
+        let mut degraded_count = 0;
+        for container_stat in &self.container_stats {
+            for proc in &container_stat.processes {
+                total_io += proc.get_stat().get_total_io_read();
+                total_io += proc.get_stat().get_total_io_write();
+                // anything that fell back to /proc carries lower-fidelity numbers
+                if proc.get_stat().get_stat_source() != process::StatSource::Taskstats {
+                    degraded_count += 1;
+                }
+            }
+        }
+
+        format!(
+            "{} containers, {} processes ({} degraded), {} io bytes, {} errors",
+            self.container_stats.len(),
+            process_count,
+            degraded_count,
+            total_io,
+            self.errors.len()
+        )
+    }
+
     pub fn new() -> Self {
         let start: SystemTime = SystemTime::now();
         let timestamp: Duration = start
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards");
 
+        let tag_host_identity = setting::get_glob_conf()
+            .unwrap()
+            .read()
+            .unwrap()
+            .get_tag_host_identity();
+        let emit_stat_semantics = setting::get_glob_conf()
+            .unwrap()
+            .read()
+            .unwrap()
+            .get_emit_stat_semantics();
+
         Self {
             container_stats: Vec::new(),
             network_rawstat: NetworkRawStat::new(),
+            errors: Vec::new(),
+            coverage: Coverage::default(),
+            tombstones: Vec::new(),
             unix_timestamp: timestamp.as_secs(),
+            unix_nanos: timestamp.as_nanos(),
+            drift_ms: None,
+            hostname: if tag_host_identity {
+                HOSTNAME.clone()
+            } else {
+                None
+            },
+            boot_id: if tag_host_identity {
+                BOOT_ID.clone()
+            } else {
+                None
+            },
+            taskstats_version: taskstat::detected_taskstats_version(),
+            semantics: if emit_stat_semantics {
+                Some(process::stat_semantics())
+            } else {
+                None
+            },
         }
     }
+
+    // collect process stats by their stable identity; processes without a
+    // process_uid (e.g. filtered out by config) can't be tracked and are skipped
+    fn stats_by_process_uid(&self) -> HashMap<String, &process::ProcessStat> {
+        let mut result = HashMap::new();
+        for container_stat in &self.container_stats {
+            for proc in &container_stat.processes {
+                let process_uid = proc.get_process_uid();
+                if !process_uid.is_empty() {
+                    result.insert(process_uid, proc.get_stat());
+                }
+            }
+        }
+        result
+    }
+
+    // compare against an earlier sample: which processes appeared, which
+    // disappeared, and how the counters of the survivors moved
+    pub fn diff(&self, prev: &TotalStat) -> TotalStatDiff {
+        let curr_stats = self.stats_by_process_uid();
+        let prev_stats = prev.stats_by_process_uid();
+
+        let mut appeared: Vec<String> = curr_stats
+            .keys()
+            .filter(|process_uid| !prev_stats.contains_key(*process_uid))
+            .cloned()
+            .collect();
+        let mut disappeared: Vec<String> = prev_stats
+            .keys()
+            .filter(|process_uid| !curr_stats.contains_key(*process_uid))
+            .cloned()
+            .collect();
+
+        let mut changed = Vec::new();
+        for (process_uid, curr_stat) in &curr_stats {
+            if let Some(prev_stat) = prev_stats.get(process_uid) {
+                let total_cpu_time_delta = curr_stat
+                    .get_total_cpu_time()
+                    .checked_sub(prev_stat.get_total_cpu_time())
+                    .unwrap_or(TimeCount::from_secs(0));
+                let total_io_read_delta = curr_stat
+                    .get_total_io_read()
+                    .checked_sub(prev_stat.get_total_io_read())
+                    .unwrap_or(DataCount::from_byte(0));
+                let total_io_write_delta = curr_stat
+                    .get_total_io_write()
+                    .checked_sub(prev_stat.get_total_io_write())
+                    .unwrap_or(DataCount::from_byte(0));
+
+                // unchanged processes stay out of the report
+                if total_cpu_time_delta != TimeCount::from_secs(0)
+                    || total_io_read_delta != DataCount::from_byte(0)
+                    || total_io_write_delta != DataCount::from_byte(0)
+                {
+                    changed.push(ProcessDiff {
+                        process_uid: process_uid.clone(),
+                        total_cpu_time_delta,
+                        total_io_read_delta,
+                        total_io_write_delta,
+                    });
+                }
+            }
+        }
+
+        // deterministic output regardless of hashmap iteration order
+        appeared.sort_unstable();
+        disappeared.sort_unstable();
+        changed.sort_unstable_by(|left, right| left.process_uid.cmp(&right.process_uid));
+
+        TotalStatDiff {
+            appeared,
+            disappeared,
+            changed,
+        }
+    }
+}
+
+// what changed between two samples, keyed by the stable process identity
+// (process_uid), for change-based publishing and interactive debugging
+#[derive(Debug, Serialize)]
+pub struct TotalStatDiff {
+    appeared: Vec<String>,
+    disappeared: Vec<String>,
+    changed: Vec<ProcessDiff>,
+}
+
+// counter movement of one process between two samples; a counter reset
+// (new value below the old one) clamps the delta to zero
+#[derive(Debug, Serialize)]
+pub struct ProcessDiff {
+    process_uid: String,
+    total_cpu_time_delta: TimeCount,
+    total_io_read_delta: DataCount,
+    total_io_write_delta: DataCount,
 }
 
 fn get_processes_stats(
     real_pid_list: &[Pid],
+    tree_mode: TreeMode,
     taskstats_conn: &TaskStatsConnection,
     net_rawstat: &mut NetworkRawStat,
+    container_name: &str,
+    errors: &mut Vec<CollectionError>,
 ) -> Result<Vec<process::Process>, DaemonError> {
     let mut processes_list = Vec::new();
     let mut iterated_pids = Vec::new();
@@ -95,81 +745,491 @@ fn get_processes_stats(
         if iterated_pids.contains(curr_real_pid) {
             continue;
         }
-        if let Ok(proc) = process::get_real_proc(curr_real_pid, taskstats_conn, net_rawstat) {
-            iterate_proc_tree(
-                &proc,
-                &mut processes_list,
-                &mut iterated_pids,
-                taskstats_conn,
-                net_rawstat,
-            );
+        match process::get_real_proc(curr_real_pid, taskstats_conn, net_rawstat) {
+            // a deliberate skip, not a collection failure
+            Err(ProcessError::KernelThreadSkipped(_)) => {}
+            Err(err) => {
+                // the pid stays out of the sample but the failure is recorded
+                errors.push(CollectionError::new(
+                    container_name,
+                    Some(*curr_real_pid),
+                    format!("{}", err),
+                ));
+            }
+            Ok(proc) => match tree_mode {
+                TreeMode::Full => iterate_proc_tree(
+                    &proc,
+                    &mut processes_list,
+                    &mut iterated_pids,
+                    taskstats_conn,
+                    net_rawstat,
+                ),
+                TreeMode::RootsOnly => iterate_proc_tree_roots_only(
+                    &proc,
+                    &mut processes_list,
+                    &mut iterated_pids,
+                    taskstats_conn,
+                    net_rawstat,
+                ),
+            },
         }
     }
 
     Ok(processes_list)
 }
 
-async fn read_monitored_data(kafka_producer: &mut Option<Producer>) -> Result<(), DaemonError> {
-    // create new taskstat connection
-    let mut taskstats_conn = TaskStatsConnection::new()?;
+// how long to wait from `now` (as a unix duration) until the next multiple
+// of `interval` on the wall clock, e.g. the next :00/:10/:20 for 10s
+fn delay_to_next_boundary(now: Duration, interval: Duration) -> Duration {
+    let interval_nanos = interval.as_nanos();
+    if interval_nanos == 0 {
+        return Duration::from_secs(0);
+    }
+
+    let into_interval = now.as_nanos() % interval_nanos;
+    if into_interval == 0 {
+        Duration::from_secs(0)
+    } else {
+        Duration::from_nanos((interval_nanos - into_interval) as u64)
+    }
+}
+
+// cached container pid list, revalidated by the mtime of the container's
+// cgroup.procs file with a max-staleness backstop so steady-state containers
+// don't cost a docker subprocess every sample
+struct ContainerPidCacheEntry {
+    real_pids: Vec<Pid>,
+    cgroup_procs_path: Option<PathBuf>,
+    cgroup_procs_mtime: Option<SystemTime>,
+    resolved_at: Instant,
+}
+
+impl ContainerPidCacheEntry {
+    fn new(real_pids: Vec<Pid>) -> Self {
+        let cgroup_procs_path = real_pids.first().and_then(cgroup_procs_path);
+        let cgroup_procs_mtime = cgroup_procs_path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+
+        Self {
+            real_pids,
+            cgroup_procs_path,
+            cgroup_procs_mtime,
+            resolved_at: Instant::now(),
+        }
+    }
+
+    fn is_fresh(&self, max_staleness_secs: u64) -> bool {
+        if self.resolved_at.elapsed() > Duration::from_secs(max_staleness_secs) {
+            return false;
+        }
+
+        // without a working change signal the cache is never trusted
+        match (&self.cgroup_procs_path, self.cgroup_procs_mtime) {
+            (Some(path), Some(mtime)) => fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .map(|curr_mtime| curr_mtime == mtime)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+// statvfs on the container's root mount via /proc/<pid>/root, so no runtime
+// query is needed; returns (total, used, avail) or None when unreadable
+fn get_container_disk_usage(real_pids: &[Pid]) -> Option<(DataCount, DataCount, DataCount)> {
+    let real_pid = real_pids.first()?;
+    let path = std::ffi::CString::new(format!("/proc/{}/root", real_pid)).ok()?;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    let block_size = stat.f_frsize as usize;
+    let total = stat.f_blocks as usize * block_size;
+    let used = (stat.f_blocks as usize).saturating_sub(stat.f_bfree as usize) * block_size;
+    let avail = stat.f_bavail as usize * block_size;
+
+    Some((
+        DataCount::from_byte(total),
+        DataCount::from_byte(used),
+        DataCount::from_byte(avail),
+    ))
+}
+
+// round-robin cursor over the monitor targets so a tick never collects more
+// than max_targets_per_tick of them; with N targets and cap C every target
+// is sampled once per ceil(N/C) ticks
+struct TargetRotation {
+    cursor: usize,
+    last_sampled: HashMap<String, Instant>,
+}
+
+impl TargetRotation {
+    fn new() -> Self {
+        Self {
+            cursor: 0,
+            last_sampled: HashMap::new(),
+        }
+    }
+
+    fn select<'a>(
+        &mut self,
+        targets: &'a [setting::MonitorTarget],
+        cap: Option<usize>,
+    ) -> Vec<&'a setting::MonitorTarget> {
+        let cap = match cap {
+            // a zero or over-sized cap degenerates to sampling everything
+            Some(cap) if cap > 0 && cap < targets.len() => cap,
+            _ => {
+                let now = Instant::now();
+                for target in targets {
+                    self.last_sampled.insert(target.container_name.clone(), now);
+                }
+                return targets.iter().collect();
+            }
+        };
+
+        let now = Instant::now();
+        let mut selected = Vec::with_capacity(cap);
+        for offset in 0..cap {
+            let target = &targets[(self.cursor + offset) % targets.len()];
+            self.last_sampled.insert(target.container_name.clone(), now);
+            selected.push(target);
+        }
+        self.cursor = (self.cursor + cap) % targets.len();
+
+        selected
+    }
+
+    // when a target was last picked for collection, for diagnostics
+    #[allow(unused)]
+    fn last_sampled(&self, container_name: &str) -> Option<Instant> {
+        self.last_sampled.get(container_name).copied()
+    }
+}
+
+// locate the cgroup.procs file of the cgroup a pid belongs to; works for
+// cgroup v2 ("0::<path>") and falls back to the first v1 controller line
+fn cgroup_procs_path(real_pid: &Pid) -> Option<PathBuf> {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", real_pid)).ok()?;
+    let mut parts = content.lines().next()?.splitn(3, ':');
+
+    let _hierarchy = parts.next()?;
+    let controller = parts.next()?;
+    let rel_path = parts.next()?;
+
+    if controller.is_empty() {
+        Some(PathBuf::from(format!("/sys/fs/cgroup{}/cgroup.procs", rel_path)))
+    } else {
+        Some(PathBuf::from(format!(
+            "/sys/fs/cgroup/{}{}/cgroup.procs",
+            controller, rel_path
+        )))
+    }
+}
+
+// scan /proc for processes whose comm or cmdline matches the pattern, the
+// host-namespace analog of asking docker which pids belong to a container
+fn find_pids_by_name_pattern(
+    pattern: &str,
+    container_name: &str,
+    errors: &mut Vec<CollectionError>,
+) -> Vec<Pid> {
+    let regex = match Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(err) => {
+            errors.push(CollectionError::new(
+                container_name,
+                None,
+                format!("invalid process_name_pattern: {}", err),
+            ));
+            return Vec::new();
+        }
+    };
+
+    let mut result = Vec::new();
+    let proc_entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push(CollectionError::new(
+                container_name,
+                None,
+                format!("can't read /proc: {}", err),
+            ));
+            return Vec::new();
+        }
+    };
+
+    for entry in proc_entries.flatten() {
+        // non-numeric entries are /proc files, not processes
+        let pid: usize = match entry.file_name().to_string_lossy().parse() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+
+        // a process gone since read_dir just doesn't match
+        let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+        let cmdline = String::from_utf8_lossy(&cmdline).replace('\0', " ");
+
+        if regex.is_match(comm.trim()) || regex.is_match(cmdline.trim()) {
+            result.push(Pid::new(pid));
+        }
+    }
+
+    result
+}
+
+// enumerate root-namespace pids belonging to the pid namespace the given
+// path (e.g. /proc/<pid>/ns/pid) points to. the ns link targets carry the
+// namespace inode ("pid:[<inode>]"), so comparing them avoids setns; the
+// trade-off is that only processes visible in the sensor's own /proc and
+// whose ns links it may read can match
+fn find_pids_by_namespace(
+    ns_path: &std::path::Path,
+    container_name: &str,
+    errors: &mut Vec<CollectionError>,
+) -> Vec<Pid> {
+    let target_ns = match fs::read_link(ns_path) {
+        Ok(target) => target,
+        Err(err) => {
+            errors.push(CollectionError::new(
+                container_name,
+                None,
+                format!("can't read pid_namespace {}: {}", ns_path.display(), err),
+            ));
+            return Vec::new();
+        }
+    };
+
+    let mut result = Vec::new();
+    let proc_entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push(CollectionError::new(
+                container_name,
+                None,
+                format!("can't read /proc: {}", err),
+            ));
+            return Vec::new();
+        }
+    };
+
+    for entry in proc_entries.flatten() {
+        // non-numeric entries are /proc files, not processes
+        let pid: usize = match entry.file_name().to_string_lossy().parse() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+
+        // an unreadable or vanished ns link just doesn't match
+        if let Ok(ns) = fs::read_link(format!("/proc/{}/ns/pid", pid)) {
+            if ns == target_ns {
+                result.push(Pid::new(pid));
+            }
+        }
+    }
+
+    result
+}
+
+// docker top column order follows the container's ps format, so locate the
+// pid column in the header instead of assuming it's second; a missing header
+// keeps the historical index
+fn docker_top_pid_column(header: Option<&str>) -> usize {
+    header
+        .and_then(|header| {
+            header
+                .split_whitespace()
+                .position(|column| column.eq_ignore_ascii_case("pid"))
+        })
+        .unwrap_or(1)
+}
+
+// ask docker which root-namespace pids belong to a container, keeping only
+// those whose namespace pid is in the target's pid_list (old kernels can't
+// translate and take everything). Ok(None) means docker was unavailable or
+// a pid raced away mid-enumeration, so the caller should skip this sample
+fn enumerate_container_pids(
+    container_name: &str,
+    pid_list: &[Pid],
+    glob_conf: &setting::DaemonConfig,
+) -> Result<Option<Vec<Pid>>, DaemonError> {
+    let mut result = Vec::new();
+    // get all process belong to that container
+    let cmd_output = match Command::new("docker").args(["top", container_name]).output() {
+        Ok(output) => output,
+        Err(_) => return Ok(None),
+    };
+
+    let stdout = match std::str::from_utf8(&cmd_output.stdout) {
+        Ok(stdout) => stdout,
+        Err(_) => return Ok(None),
+    };
+    let mut lines = stdout.lines();
+    let pid_column = docker_top_pid_column(lines.next());
+
+    for line in lines {
+        // get that process pid
+        let columns = line.split_whitespace().collect::<Vec<&str>>();
+        let real_pid = match columns.get(pid_column) {
+            Some(pid) => Pid::new(pid.parse()?),
+            None => continue,
+        };
+
+        if glob_conf.is_old_kernel() {
+            result.push(real_pid);
+            continue;
+        }
+
+        // get pid inside namespace
+        let file_status_content = match fs::read_to_string(format!("/proc/{}/status", real_pid)) {
+            Ok(content) => content,
+            Err(_) => return Ok(None),
+        };
+
+        let content_lines: Vec<&str> = file_status_content.lines().collect();
+
+        // namespaced pid from the NSpid line looked up by name; a missing
+        // or malformed line skips the process instead of panicking
+        let pid = match process::status_value(&content_lines, "NSpid:")
+            .and_then(|values| values.split_whitespace().last())
+            .and_then(|pid| Pid::try_from(pid).ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        // check if pid is needed
+        if pid_list.contains(&pid) {
+            result.push(real_pid);
+        }
+    }
+
+    Ok(Some(result))
+}
+
+async fn read_monitored_data(
+    sink: &mut dyn OutputSink,
+    drift_ms: Option<u64>,
+    prev_process_stats: &mut HashMap<String, process::ProcessStat>,
+    container_pid_cache: &mut HashMap<String, ContainerPidCacheEntry>,
+    target_rotation: &mut TargetRotation,
+    enricher: Option<&enrichment::Enricher<IpAddr, String>>,
+    prev_total_stat: &mut Option<TotalStat>,
+) -> Result<(), DaemonError> {
+    // create new taskstat connection, retrying per the netlink policy
+    let netlink_retry = setting::get_glob_conf()?.read().unwrap().get_netlink_retry();
+    let mut taskstats_conn = retry::retry(&netlink_retry, TaskStatsConnection::new).await?;
 
     // listen for connection
 
     let mut total_stat = TotalStat::new();
+    total_stat.drift_ms = drift_ms;
+
+    // get network raw stat, a capture hiccup shouldn't abort the whole sample
+    let collect_network = setting::get_glob_conf()?.read().unwrap().get_collect_network();
+    total_stat.network_rawstat = if collect_network {
+        match network_stat::get_network_rawstat() {
+            Ok(network_rawstat) => network_rawstat,
+            Err(err) => {
+                println!("warning: network rawstat unavailable this sample: {}", err);
+                total_stat.errors.push(CollectionError::new(
+                    "_network",
+                    None,
+                    format!("{}", err),
+                ));
+                NetworkRawStat::new()
+            }
+        }
+    } else {
+        NetworkRawStat::new()
+    };
+
+    // prebuild the inode → connection-stat index so the per-process fd
+    // scans attribute each socket with a single lookup
+    process::build_connection_stat_index(&mut total_stat.network_rawstat);
+
+    // best-effort reverse dns on connection remotes: queue lookups for this
+    // sample's remotes and attach whatever earlier samples already resolved
+    if let Some(enricher) = enricher {
+        let remote_addrs: HashSet<IpAddr> = total_stat
+            .network_rawstat
+            .connection_entries()
+            .map(|(_, connection)| connection.get_remote_addr())
+            .collect();
 
-    // get network raw stat
-    total_stat.network_rawstat = network_stat::get_network_rawstat()?;
+        for remote_addr in remote_addrs {
+            enricher.request(remote_addr, move || enrichment::reverse_dns(remote_addr));
+            if let Some(hostname) = enricher.get(&remote_addr) {
+                total_stat
+                    .network_rawstat
+                    .attach_remote_hostname(remote_addr, hostname);
+            }
+        }
+    }
 
     // get global config
     let borrowing = setting::get_glob_conf()?;
     let glob_conf = borrowing.read().unwrap();
 
-    // for each monitor target
-    'monitorLoop: for monitor_target in &glob_conf.get_monitor_targets() {
-        // get needed process list
-        let real_pid_list = if monitor_target.container_name != "/" {
-            let mut result = Vec::new();
-            // get all process belong to that container
-            let cmd_output = match Command::new("docker")
-                .args(["top", &monitor_target.container_name])
-                .output()
-            {
-                Ok(output) => output,
-                Err(_) => continue,
-            };
-
-            let lines: Vec<&str> = std::str::from_utf8(&cmd_output.stdout)
-                .unwrap()
-                .lines()
-                .skip(1)
-                .collect::<Vec<&str>>();
-
-            for line in lines {
-                // get that process pid
-                let real_pid = Pid::new(line.split_whitespace().collect::<Vec<&str>>()[1].parse()?);
-
-                if glob_conf.is_old_kernel() {
-                    result.push(real_pid);
-                    continue;
-                }
-
-                // get pid inside namespace
-                let file_status_content =
-                    match fs::read_to_string(format!("/proc/{}/status", real_pid)) {
-                        Ok(content) => content,
-                        Err(_) => continue 'monitorLoop,
-                    };
+    // for each monitor target; a network-only sensor skips this phase entirely
+    let monitor_targets = if glob_conf.get_collect_process() {
+        glob_conf.get_monitor_targets()
+    } else {
+        Vec::new()
+    };
 
-                let content_lines: Vec<&str> = file_status_content.lines().collect();
+    'monitorLoop: for monitor_target in
+        target_rotation.select(&monitor_targets, glob_conf.get_max_targets_per_tick())
+    {
+        let mut collection_errors = Vec::new();
 
-                // get pid
-                let pids = content_lines[12].split_whitespace().collect::<Vec<&str>>();
-                let pid = Pid::try_from(pids[pids.len() - 1]).unwrap();
+        // get needed process list
+        let real_pid_list = if let Some(pattern) = &monitor_target.process_name_pattern {
+            // bare-host targeting, the matched pids are root-namespace pids
+            find_pids_by_name_pattern(
+                pattern,
+                &monitor_target.container_name,
+                &mut collection_errors,
+            )
+        } else if let Some(ns_path) = &monitor_target.pid_namespace {
+            // the matched pids are root-namespace pids, like the pattern case
+            find_pids_by_namespace(
+                ns_path,
+                &monitor_target.container_name,
+                &mut collection_errors,
+            )
+        } else if let Some(cached_pids) = glob_conf
+            .get_container_pid_cache_secs()
+            .filter(|_| monitor_target.container_name != "/")
+            .and_then(|max_staleness_secs| {
+                container_pid_cache
+                    .get(&monitor_target.container_name)
+                    .filter(|entry| entry.is_fresh(max_staleness_secs))
+                    .map(|entry| entry.real_pids.clone())
+            })
+        {
+            // the container's cgroup is unchanged, skip re-enumeration
+            cached_pids
+        } else if monitor_target.container_name != "/" {
+            let result = match enumerate_container_pids(
+                &monitor_target.container_name,
+                &monitor_target.pid_list,
+                &glob_conf,
+            )? {
+                Some(pids) => pids,
+                // docker unavailable or a pid raced away, skip this sample
+                None => continue 'monitorLoop,
+            };
 
-                // check if pid is needed
-                if monitor_target.pid_list.contains(&pid) {
-                    result.push(real_pid);
-                }
+            if glob_conf.get_container_pid_cache_secs().is_some() {
+                container_pid_cache.insert(
+                    monitor_target.container_name.clone(),
+                    ContainerPidCacheEntry::new(result.clone()),
+                );
             }
 
             result
@@ -177,86 +1237,436 @@ async fn read_monitored_data(kafka_producer: &mut Option<Producer>) -> Result<()
             monitor_target.pid_list.clone()
         };
 
+        // cap a runaway container to a deterministic subset (lowest pids
+        // first) so one target can't dominate the sample
+        let mut pids_truncated = false;
+        let real_pid_list = match monitor_target.max_pids {
+            Some(max_pids) if real_pid_list.len() > max_pids => {
+                let mut capped = real_pid_list;
+                capped.sort_unstable();
+                capped.truncate(max_pids);
+                pids_truncated = true;
+                capped
+            }
+            _ => real_pid_list,
+        };
+
         // get stats
         match get_processes_stats(
             &real_pid_list,
+            monitor_target.tree_mode,
             &mut taskstats_conn,
             &mut total_stat.network_rawstat,
+            &monitor_target.container_name,
+            &mut collection_errors,
         ) {
             Ok(processes) => {
+                total_stat.errors.append(&mut collection_errors);
+
+                // skip containers that matched no pids unless the user wants presence signals
+                if processes.is_empty() && !glob_conf.get_serialize_empty_containers() {
+                    continue;
+                }
+
+                let coverage = Coverage::for_processes(&real_pid_list, &processes);
+                total_stat.coverage += coverage;
+
+                // container filesystem usage, reached through the first
+                // pid's root mount; unreadable mounts leave all three unset
+                let disk_usage = if glob_conf.get_collect_disk_usage() {
+                    get_container_disk_usage(&real_pid_list)
+                } else {
+                    None
+                };
+
+                // aggregate mode keeps only the per-container sums and
+                // drops the individual process objects from the payload
+                let (processes, aggregated_stat) = match glob_conf.get_container_detail() {
+                    setting::ContainerDetail::Full => (processes, None),
+                    setting::ContainerDetail::Aggregate => {
+                        let mut summed_stat = process::ProcessStat::new();
+                        for proc in &processes {
+                            summed_stat += proc.get_stat().clone();
+                        }
+                        (Vec::new(), Some(summed_stat))
+                    }
+                };
+
                 // add stat to new container stat
                 let container_stat = ContainerStat {
                     container_name: monitor_target.container_name.clone(),
                     processes,
+                    aggregated_stat,
+                    coverage,
+                    pids_truncated,
+                    disk_total: disk_usage.map(|(total, _, _)| total),
+                    disk_used: disk_usage.map(|(_, used, _)| used),
+                    disk_avail: disk_usage.map(|(_, _, avail)| avail),
                 };
 
                 total_stat.container_stats.push(container_stat);
             }
             Err(err) => {
-                println!("error: {}", err);
+                // repeated per-target failures collapse into one line per window
+                let err_text = format!("{}", err);
+                log_error_limited(
+                    &format!("{}:{}", monitor_target.container_name, err_text),
+                    &err_text,
+                );
+                total_stat.errors.append(&mut collection_errors);
+                total_stat.errors.push(CollectionError::new(
+                    &monitor_target.container_name,
+                    None,
+                    err_text,
+                ));
                 continue;
             }
         }
     }
 
+    // add a synthetic "_host" entry with the summed stats of all targets
+    if glob_conf.get_emit_host_aggregate() {
+        let host_stat = host_aggregate_stat(&total_stat.container_stats);
+
+        total_stat.container_stats.push(ContainerStat {
+            container_name: String::from("_host"),
+            processes: Vec::new(),
+            aggregated_stat: Some(host_stat),
+            coverage: Coverage::default(),
+            pids_truncated: false,
+            disk_total: None,
+            disk_used: None,
+            disk_avail: None,
+        });
+    }
+
     // clean up network raw stat
     total_stat
         .network_rawstat
         .remove_unused_uni_connection_stats();
 
+    // refresh after collection so even the very first sample reports the
+    // version its own queries just detected
+    total_stat.taskstats_version = taskstat::detected_taskstats_version();
+
+    // per-interval deltas against the previous sample, keyed by the stable
+    // process identity so pid reuse starts a fresh series. tombstones reuse
+    // the same previous-sample table
+    let emit_deltas = glob_conf.get_emit_deltas();
+    let emit_tombstones = glob_conf.get_emit_tombstones();
+    if emit_deltas || emit_tombstones {
+        let mut curr_process_stats = HashMap::new();
+        for container_stat in &mut total_stat.container_stats {
+            for proc in &mut container_stat.processes {
+                let process_uid = proc.get_process_uid();
+                if emit_deltas {
+                    proc.compute_stat_deltas(prev_process_stats.get(&process_uid));
+                }
+                curr_process_stats.insert(process_uid, proc.get_stat().clone());
+            }
+        }
+
+        if emit_tombstones {
+            total_stat
+                .tombstones
+                .extend(tombstones_for(prev_process_stats, &curr_process_stats));
+        }
+
+        *prev_process_stats = curr_process_stats;
+    }
+
+    // log how this sample moved against the previous one; the previous
+    // sample is only retained while the flag is on
+    if glob_conf.get_log_sample_diff() {
+        if let Some(prev) = prev_total_stat.as_ref() {
+            println!(
+                "sample diff: {}",
+                serde_json::to_string(&total_stat.diff(prev)).unwrap()
+            );
+        }
+        *prev_total_stat = Some(total_stat.clone());
+    } else {
+        *prev_total_stat = None;
+    }
+
+    // append the whole sample as one ndjson line for simple file pipelines
+    if let Some(ndjson_path) = glob_conf.get_ndjson_output() {
+        if let Err(err) =
+            append_ndjson_sample(&ndjson_path, glob_conf.get_ndjson_max_bytes(), &total_stat)
+        {
+            println!("error: can't append to {}: {}", ndjson_path, err);
+        }
+    }
+
     // return result
 
-    let dev_flag = glob_conf.get_dev_flag();
     let message_chunk_size = glob_conf.get_message_chunk_size();
-    let results_as_str = serde_json::to_string(&total_stat).unwrap();
-    let messages = if let Some(size) = message_chunk_size {
-        results_as_str
-            .chars()
-            .collect::<Vec<char>>()
-            .chunks(size)
-            .map(|c| c.iter().collect::<String>())
-            .collect::<Vec<String>>()
-    } else {
-        vec![results_as_str; 1]
+    let payload_compression = glob_conf.get_payload_compression();
+    let messages = match glob_conf.get_output_shape() {
+        // one flat record per process, no chunking needed
+        setting::OutputShape::Flat => flat_records(&total_stat, payload_compression),
+        // line protocol goes out uncompressed so telegraf can ingest it as-is
+        setting::OutputShape::Influx => {
+            let mut records = Vec::new();
+            for container_stat in &total_stat.container_stats {
+                for proc in &container_stat.processes {
+                    records.push(process_to_line_protocol(
+                        &glob_conf.get_name(),
+                        &container_stat.container_name,
+                        proc,
+                        total_stat.unix_nanos,
+                    ));
+                }
+            }
+            records
+        }
+        setting::OutputShape::Tree => {
+            // compress before chunking so the chunks cover the compressed bytes
+            let results_as_str = compress_payload(
+                serde_json::to_string(&total_stat).unwrap(),
+                payload_compression,
+            );
+            chunk_payload(results_as_str, message_chunk_size)
+        }
     };
 
-    let mut i = 0;
     let cluster_name = glob_conf.get_cluster();
     let sensor_name = glob_conf.get_name();
 
+    sink.begin_sample();
     for message in messages.iter() {
         let msg_chunk = MessageChunk::new(
             sensor_name.clone(),
             cluster_name.clone(),
             message.to_owned(),
+            payload_compression,
         );
-        if dev_flag {
-            let _ = fs::write(
-                format!("./results/chunk_{}.json", i),
-                serde_json::to_string(&msg_chunk).unwrap(),
-            );
-            println!("Wrote to results/chunk_{}.json", i);
-        } else {
-            kafka_producer
-                .as_mut()
-                .unwrap()
-                .send(&Record::from_value(
-                    &format!("monitoring"),
-                    serde_json::to_string(&msg_chunk).unwrap(),
-                ))
-                .unwrap();
-            println!("Sent to kafka !");
+        // the sink blocks (and retries) on its transport, so step off the
+        // runtime's cooperative scheduling while it does
+        if let Err(err) = task::block_in_place(|| sink.publish(&msg_chunk)) {
+            // the sink already retried per its policy; route the chunk to
+            // the dead-letter file instead of dropping it silently
+            match glob_conf.get_dead_letter_path() {
+                Some(path) => {
+                    if let Err(dl_err) = append_dead_letter(&path, &msg_chunk, &err) {
+                        println!("error: can't publish chunk: {}", err);
+                        println!("error: can't write dead letter {}: {}", path, dl_err);
+                    } else {
+                        println!("error: can't publish chunk, dead-lettered to {}: {}", path, err);
+                    }
+                }
+                None => println!("error: can't publish chunk: {}", err),
+            }
         }
-        i += 1;
     }
+    println!("{}", total_stat.summary());
     println!("==========");
 
     Ok(())
 }
 
+// append a chunk that gave up publishing, one json line per chunk with the
+// failure reason so the payload can be inspected or replayed later
+fn append_dead_letter(
+    path: &str,
+    chunk: &MessageChunk,
+    err: &SinkError,
+) -> Result<(), io::Error> {
+    let record = serde_json::json!({
+        "unix_timestamp": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        "error": err.to_string(),
+        "chunk": chunk,
+    });
+
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", record)?;
+    file.flush()
+}
+
+// serialize the sample to a single line and append it, rotating the file to
+// <path>.1 once it grows past max_bytes
+fn append_ndjson_sample(
+    path: &str,
+    max_bytes: Option<u64>,
+    total_stat: &TotalStat,
+) -> Result<(), io::Error> {
+    if let Some(max_bytes) = max_bytes {
+        if let Ok(metadata) = fs::metadata(path) {
+            if metadata.len() >= max_bytes {
+                fs::rename(path, format!("{}.1", path))?;
+            }
+        }
+    }
+
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(total_stat).unwrap())?;
+    file.flush()
+}
+
+// one-shot support dump: collect a single configured container and write
+// its stats as a standalone pretty-printed json document, outside the
+// normal publish path
+fn dump_container(container_name: &str, output_path: &str) -> Result<(), DaemonError> {
+    let borrowing = setting::get_glob_conf()?;
+    let glob_conf = borrowing.read().unwrap();
+
+    let monitor_target = glob_conf
+        .get_monitor_targets()
+        .into_iter()
+        .find(|target| target.container_name == container_name)
+        .ok_or_else(|| DaemonError::UnknownContainer(String::from(container_name)))?;
+
+    let netlink_retry = glob_conf.get_netlink_retry();
+    let taskstats_conn = retry::retry_blocking(&netlink_retry, TaskStatsConnection::new)?;
+
+    // a capture hiccup degrades the dump to process stats only, like the
+    // monitoring loop does for a sample
+    let mut net_rawstat = if glob_conf.get_collect_network() {
+        network_stat::get_network_rawstat().unwrap_or_else(|err| {
+            println!("warning: network rawstat unavailable for dump: {}", err);
+            NetworkRawStat::new()
+        })
+    } else {
+        NetworkRawStat::new()
+    };
+    process::build_connection_stat_index(&mut net_rawstat);
+
+    let mut collection_errors = Vec::new();
+    let real_pid_list = if let Some(pattern) = &monitor_target.process_name_pattern {
+        find_pids_by_name_pattern(pattern, container_name, &mut collection_errors)
+    } else if let Some(ns_path) = &monitor_target.pid_namespace {
+        find_pids_by_namespace(ns_path, container_name, &mut collection_errors)
+    } else if container_name != "/" {
+        enumerate_container_pids(container_name, &monitor_target.pid_list, &glob_conf)?
+            .ok_or_else(|| DaemonError::UnknownContainer(String::from(container_name)))?
+    } else {
+        monitor_target.pid_list.clone()
+    };
+
+    let processes = get_processes_stats(
+        &real_pid_list,
+        monitor_target.tree_mode,
+        &taskstats_conn,
+        &mut net_rawstat,
+        container_name,
+        &mut collection_errors,
+    )?;
+
+    for collection_error in &collection_errors {
+        println!("warning: {:?}", collection_error);
+    }
+
+    let coverage = Coverage::for_processes(&real_pid_list, &processes);
+    let disk_usage = if glob_conf.get_collect_disk_usage() {
+        get_container_disk_usage(&real_pid_list)
+    } else {
+        None
+    };
+
+    let container_stat = ContainerStat {
+        container_name: String::from(container_name),
+        processes,
+        aggregated_stat: None,
+        coverage,
+        pids_truncated: false,
+        disk_total: disk_usage.map(|(total, _, _)| total),
+        disk_used: disk_usage.map(|(_, used, _)| used),
+        disk_avail: disk_usage.map(|(_, _, avail)| avail),
+    };
+
+    fs::write(
+        output_path,
+        serde_json::to_string_pretty(&container_stat).unwrap(),
+    )?;
+    println!(
+        "dumped container '{}' ({} processes) to {}",
+        container_name,
+        container_stat.processes.len(),
+        output_path
+    );
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), DaemonError> {
+    // the collectors need taskstats and /proc, so bail out early off-linux
+    #[cfg(not(target_os = "linux"))]
+    return Err(DaemonError::Unsupported);
+
+    #[cfg(target_os = "linux")]
+    return run().await;
+}
+
+// one-shot triage banner: what this sensor is, where it publishes, what it
+// watches, and which kernel facilities it actually found at startup
+fn build_startup_banner(taskstats_available: bool, capture_initialized: bool) -> String {
+    let binding = setting::get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+
+    let kernel_version = fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|value| value.trim().to_string())
+        .unwrap_or_else(|_| String::from("unknown"));
+
+    // dev mode forces the file sink, mirror what the monitoring task does
+    let sink_kind = if glob_conf.get_dev_flag() {
+        setting::OutputSinkKind::File
+    } else {
+        glob_conf.get_output_sink()
+    };
+
+    let mut banner = format!(
+        "sensor '{}' in cluster '{}', publishing every {}s via {:?} sink\n",
+        glob_conf.get_name(),
+        glob_conf.get_cluster(),
+        glob_conf.get_publish_msg_interval(),
+        sink_kind,
+    );
+    banner.push_str(&format!(
+        "kernel {}, taskstats {}, network capture {}\n",
+        kernel_version,
+        if taskstats_available {
+            "available"
+        } else {
+            "unavailable"
+        },
+        if capture_initialized {
+            "initialized"
+        } else {
+            "disabled"
+        },
+    ));
+
+    for monitor_target in glob_conf.get_monitor_targets() {
+        banner.push_str(&format!("  {}\n", monitor_target.describe()));
+    }
+
+    banner
+}
+
+#[cfg(target_os = "linux")]
+async fn run() -> Result<(), DaemonError> {
     dotenv().ok();
+
+    // one-shot support dump, no sinks and no env requirements
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("dump-container") {
+        let (container_name, output_path) = match (args.get(2), args.get(3)) {
+            (Some(container_name), Some(output_path)) => (container_name, output_path),
+            _ => {
+                return Err(DaemonError::UsageErr(String::from(
+                    "dump-container <name> <path> [config]",
+                )))
+            }
+        };
+        let config_path = args.get(4).map(String::as_str).unwrap_or("config.toml");
+        setting::init_glob_conf(config_path)?;
+        return dump_container(container_name, output_path);
+    }
+
     let redis_connection_url =
         std::env::var("REDIS_CONNECTION_URL").expect("REDIS_CONNECTION_URL must be set.");
     let kafka_connection_url =
@@ -269,57 +1679,279 @@ async fn main() -> Result<(), DaemonError> {
     };
 
     setting::init_glob_conf(config_path.as_str())?;
-    network_stat::init_network_stat_capture()?;
+
+    // a process-only sensor never starts the capture threads at all
+    let mut capture_initialized = false;
+    if setting::get_glob_conf()?.read().unwrap().get_collect_network() {
+        match network_stat::init_network_stat_capture() {
+            Ok(()) => capture_initialized = true,
+            Err(err) => {
+                let capture_optional =
+                    setting::get_glob_conf()?.read().unwrap().get_capture_optional();
+                if capture_optional {
+                    // degraded mode, samples go out without network stats
+                    println!("warning: network capture disabled: {}", err);
+                } else {
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+
+    // a throwaway probe connection, just to report availability in the banner
+    let taskstats_available = TaskStatsConnection::new().is_ok();
+    print!(
+        "{}",
+        build_startup_banner(taskstats_available, capture_initialized)
+    );
 
     let monitoring_task = task::spawn(async move {
         let glob_conf = setting::get_glob_conf().unwrap();
-        let mut kafka_producer = if !glob_conf.read().unwrap().get_dev_flag() {
-            Some(
+        // pick the transport once; dev mode keeps forcing the file sink
+        let sink_kind = if glob_conf.read().unwrap().get_dev_flag() {
+            setting::OutputSinkKind::File
+        } else {
+            glob_conf.read().unwrap().get_output_sink()
+        };
+
+        let mut sink: Option<Box<dyn OutputSink>> = Some(match sink_kind {
+            setting::OutputSinkKind::Kafka => Box::new(KafkaSink::new(
                 Producer::from_hosts(vec![kafka_connection_url])
                     .with_ack_timeout(Duration::from_secs(1))
                     .with_required_acks(RequiredAcks::One)
                     .create()
                     .unwrap(),
-            )
+            )),
+            setting::OutputSinkKind::File => Box::new(FileSink::new()),
+            setting::OutputSinkKind::Stdout => Box::new(StdoutSink),
+        });
+
+        let interval_duration =
+            Duration::from_secs(glob_conf.read().unwrap().get_publish_msg_interval());
+
+        // optionally hold the first tick until the next wall-clock interval
+        // boundary so samples across the fleet line up
+        let mut interval = if glob_conf.read().unwrap().get_align_to_clock() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            let initial_delay = delay_to_next_boundary(now, interval_duration);
+            time::interval_at(time::Instant::now() + initial_delay, interval_duration)
+        } else {
+            time::interval(interval_duration)
+        };
+        // the executor outlives the loop so lookups finished after one
+        // sample attach on the next
+        let enricher = if glob_conf.read().unwrap().get_enrich_remote_hostnames() {
+            Some(enrichment::Enricher::new(
+                glob_conf.read().unwrap().get_enrichment_max_concurrency(),
+                Duration::from_millis(glob_conf.read().unwrap().get_enrichment_timeout_ms()),
+            ))
         } else {
             None
         };
 
-        let mut interval = time::interval(Duration::from_secs(
-            glob_conf.read().unwrap().get_publish_msg_interval(),
-        ));
+        let mut drift_ms: Option<u64> = None;
+        let mut prev_total_stat: Option<TotalStat> = None;
+        let mut prev_process_stats = HashMap::new();
+        let mut container_pid_cache = HashMap::new();
+        let mut target_rotation = TargetRotation::new();
         loop {
-            interval.tick().await;
-            let _ = read_monitored_data(&mut kafka_producer).await;
+            tokio::select! {
+                _ = interval.tick() => {
+                    let collect_start = SystemTime::now();
+                    if let Err(err) = read_monitored_data(
+                        sink.as_deref_mut().unwrap(),
+                        drift_ms,
+                        &mut prev_process_stats,
+                        &mut container_pid_cache,
+                        &mut target_rotation,
+                        enricher.as_ref(),
+                        &mut prev_total_stat,
+                    )
+                    .await
+                    {
+                        let err_text = format!("{}", err);
+                        log_error_limited(&err_text, &err_text);
+                    }
+
+                    // a collection slower than the interval means we publish late
+                    let elapsed = collect_start.elapsed().unwrap_or_default();
+                    drift_ms = if elapsed > interval_duration {
+                        let overrun = (elapsed - interval_duration).as_millis() as u64;
+                        println!(
+                            "warning: collection overran the publish interval by {}ms",
+                            overrun
+                        );
+                        Some(overrun)
+                    } else {
+                        None
+                    };
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    // stop sampling and push anything still buffered through the
+                    // sink, bounded so a hung transport can't wedge shutdown
+                    let mut sink = sink.take().unwrap();
+                    let flush_timeout = Duration::from_secs(
+                        glob_conf
+                            .read()
+                            .unwrap()
+                            .get_shutdown_flush_timeout_secs()
+                            .unwrap_or(5),
+                    );
+
+                    println!("shutting down, flushing output sink");
+                    let flush_task = task::spawn_blocking(move || sink.flush());
+                    match time::timeout(flush_timeout, flush_task).await {
+                        Ok(Ok(Ok(()))) => {}
+                        Ok(Ok(Err(err))) => {
+                            println!("warning: can't flush sink at shutdown: {}", err)
+                        }
+                        Ok(Err(_)) | Err(_) => println!(
+                            "warning: sink flush didn't finish within {}s",
+                            flush_timeout.as_secs()
+                        ),
+                    }
+                    break;
+                }
+            }
         }
     });
 
     let serve_config_task_change = task::spawn(async move {
+        let redis_retry = setting::get_glob_conf()
+            .unwrap()
+            .read()
+            .unwrap()
+            .get_redis_retry();
         let redis_client = redis::Client::open(redis_connection_url).unwrap();
-        let mut connection = redis_client.get_connection().unwrap();
-        let mut pubsub = connection.as_pubsub();
-        pubsub.subscribe(format!("/update/config/1915940")).unwrap();
 
+        let (config_channels, config_ack_channel) = {
+            let glob_conf = setting::get_glob_conf().unwrap();
+            let glob_conf = glob_conf.read().unwrap();
+            (
+                glob_conf.get_config_channels(),
+                glob_conf.get_config_ack_channel(),
+            )
+        };
+
+        // async connections so a slow redis yields instead of blocking a
+        // runtime thread; a dropped subscription rebuilds both connections
+        let mut attempt = 0;
         loop {
-            let msg = pubsub.get_message().unwrap();
-            let payload: String = msg.get_payload().unwrap();
-            match update_glob_conf(config_path.clone(), payload) {
-                Ok(()) => {
-                    println!("Config changes")
+            let connections = async {
+                let publish_connection = redis_client.get_async_connection().await?;
+                let mut pubsub = redis_client.get_async_connection().await?.into_pubsub();
+
+                if config_channels.is_empty() {
+                    pubsub.subscribe(format!("/update/config/1915940")).await?;
+                } else {
+                    for config_channel in &config_channels {
+                        pubsub.subscribe(config_channel).await?;
+                    }
                 }
+
+                Ok::<_, redis::RedisError>((publish_connection, pubsub))
+            };
+
+            let (mut publish_connection, pubsub) = match connections.await {
+                Ok(connections) => connections,
                 Err(err) => {
-                    println!("{}", err)
+                    println!("warning: can't subscribe to redis: {}", err);
+                    attempt += 1;
+                    if attempt >= redis_retry.max_attempts {
+                        panic!("can't subscribe to redis: {}", err);
+                    }
+                    time::sleep(redis_retry.delay_for_attempt(attempt - 1)).await;
+                    continue;
                 }
             };
+            attempt = 0;
+
+            let mut messages = pubsub.into_on_message();
+            while let Some(msg) = messages.next().await {
+                handle_config_message(
+                    &mut publish_connection,
+                    &config_path,
+                    &config_ack_channel,
+                    msg,
+                )
+                .await;
+            }
+
+            println!("warning: redis subscription closed, reconnecting");
         }
     });
 
-    match tokio::join!(serve_config_task_change, monitoring_task).0 {
+    // the config task is a pure reconnect loop with no exit of its own, so
+    // once the monitoring task has flushed and stopped, abort it instead of
+    // joining it — otherwise shutdown hangs until a kill
+    let result = monitoring_task.await;
+    serve_config_task_change.abort();
+    let _ = serve_config_task_change.await;
+
+    match result {
         Ok(_) => Ok(()),
         Err(_) => Err(DaemonError::UnknownErr),
     }
 }
 
+// the ack published on the control channel for one config update outcome
+fn config_ack_payload(
+    config_version: &str,
+    update_result: &Result<(), ConfigError>,
+) -> serde_json::Value {
+    match update_result {
+        Ok(()) => serde_json::json!({
+            "config_version": config_version,
+            "success": true,
+        }),
+        Err(err) => serde_json::json!({
+            "config_version": config_version,
+            "success": false,
+            "error": format!("{}", err),
+        }),
+    }
+}
+
+// apply one config update payload and ack it on the control channel when set
+#[cfg(target_os = "linux")]
+async fn handle_config_message(
+    publish_connection: &mut redis::aio::Connection,
+    config_path: &str,
+    config_ack_channel: &Option<String>,
+    msg: redis::Msg,
+) {
+    let payload: String = match msg.get_payload() {
+        Ok(payload) => payload,
+        Err(err) => {
+            println!("warning: can't read config payload: {}", err);
+            return;
+        }
+    };
+
+    // version the payload so the control plane can match acks to updates
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    let config_version = format!("{:016x}", hasher.finish());
+
+    let update_result = update_glob_conf(config_path.to_owned(), payload);
+    match &update_result {
+        Ok(()) => println!("Config changes"),
+        Err(err) => println!("{}", err),
+    }
+    let ack = config_ack_payload(&config_version, &update_result);
+
+    if let Some(config_ack_channel) = config_ack_channel {
+        let publish_result: Result<(), redis::RedisError> = publish_connection
+            .publish(config_ack_channel, ack.to_string())
+            .await;
+        if let Err(err) = publish_result {
+            println!("Can't publish config ack: {}", err);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DaemonError {
     NetworkStatErr(NetworkStatError),
@@ -330,6 +1962,9 @@ pub enum DaemonError {
     ProcessErr(ProcessError),
     ListenThreadErr(Box<dyn Any + Send>),
     ParseIntErr(std::num::ParseIntError),
+    Unsupported,
+    UnknownContainer(String),
+    UsageErr(String),
     UnknownErr,
 }
 
@@ -352,6 +1987,14 @@ impl fmt::Display for DaemonError {
                 String::from(format!("Listen thread error: {:?}", listen_thread_err))
             }
             Self::ParseIntErr(error) => String::from(format!("Parse integer error: {}", error)),
+            Self::Unsupported => {
+                String::from("This daemon only supports linux, other platforms can only compile it")
+            }
+            Self::UnknownContainer(container_name) => String::from(format!(
+                "Container '{}' is not in the monitor targets",
+                container_name
+            )),
+            Self::UsageErr(usage) => String::from(format!("Usage: {}", usage)),
             Self::UnknownErr => String::from("This error is not implemented"),
         };
 
@@ -394,3 +2037,388 @@ impl From<std::num::ParseIntError> for DaemonError {
         Self::ParseIntErr(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{Count, Gid, Timestamp, Uid};
+    use crate::process::{Pid, Tid};
+    use crate::taskstat::{TaskStats, TaskStatsError, TaskStatsSource};
+
+    fn test_process(real_pid: usize) -> process::Process {
+        process::Process::new(
+            Pid::new(real_pid),
+            Pid::new(1),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Pid::new(real_pid),
+            Pid::new(1),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            String::from("/bin/stub"),
+            String::from("stub"),
+        )
+    }
+
+    // a source that hands back one fixed taskstats sample for any query
+    struct FixedStats(TaskStats);
+
+    impl TaskStatsSource for FixedStats {
+        fn thread_taskstats(&self, _real_tid: Tid) -> Result<TaskStats, TaskStatsError> {
+            Ok(self.0.clone())
+        }
+
+        fn process_taskstats(&self, _real_pid: Pid) -> Result<TaskStats, TaskStatsError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    // a process stat with known cpu/io numbers, built through the public
+    // thread-accumulation path
+    fn process_stat_with(cpu_nanos: usize, io_bytes: usize) -> process::ProcessStat {
+        let stats = TaskStats {
+            command_str: String::from("stub"),
+            pid: Pid::new(1),
+            uid: Uid::new(0),
+            gid: Gid::new(0),
+            parent_pid: Pid::new(1),
+            nice: 0,
+            flags: 0,
+            exitcode: 0,
+            timestamp: Timestamp::new(),
+            begin_time: UNIX_EPOCH,
+            elapsed_time: TimeCount::from_secs(1),
+            scheduling_discipline: 0,
+            user_cpu_time: TimeCount::from_nanosecs(cpu_nanos),
+            system_cpu_time: TimeCount::from_nanosecs(0),
+            accumulated_rss: DataCount::from_byte(0),
+            accumulated_vss: DataCount::from_byte(0),
+            high_water_rss: DataCount::from_byte(0),
+            high_water_vss: DataCount::from_byte(0),
+            io_read: DataCount::from_byte(io_bytes),
+            io_write: DataCount::from_byte(0),
+            read_syscall_count: Count::new(0),
+            write_syscall_count: Count::new(0),
+            block_io_read: DataCount::from_byte(0),
+            block_io_write: DataCount::from_byte(0),
+            cancelled_block_io_write: DataCount::from_byte(0),
+            cpu_delay_count: Count::new(0),
+            cpu_delay_total: TimeCount::from_secs(0),
+            minor_fault_count: Count::new(0),
+            major_fault_count: Count::new(0),
+            free_pages_delay_count: Count::new(0),
+            free_pages_delay_total: TimeCount::from_secs(0),
+            thrashing_delay_count: Count::new(0),
+            thrashing_delay_total: TimeCount::from_secs(0),
+            block_io_delay_count: Count::new(0),
+            block_io_delay_total: TimeCount::from_secs(0),
+            swapin_delay_count: Count::new(0),
+            swapin_delay_total: TimeCount::from_secs(0),
+            memory_compact_delay_count: Count::new(0),
+            memory_compact_delay_total: TimeCount::from_secs(0),
+            voluntary_context_switches: Count::new(0),
+            nonvoluntary_context_switches: Count::new(0),
+            cpu_runtime_real_total: TimeCount::from_secs(0),
+            cpu_runtime_virtual_total: TimeCount::from_secs(0),
+            user_time_scaled: TimeCount::from_secs(0),
+            system_time_scaled: TimeCount::from_secs(0),
+            run_real_total_scaled: TimeCount::from_secs(0),
+        };
+
+        let mut thread = process::Thread::new(Tid::new(1), Pid::new(1), Tid::new(1), Pid::new(1));
+        let thread_stat = thread.get_stat(&FixedStats(stats)).unwrap();
+
+        let mut stat = process::ProcessStat::new();
+        stat += thread_stat;
+        stat
+    }
+
+    // a process with a fixed identity and known cpu/io numbers
+    fn process_with(process_uid: &str, cpu_nanos: usize, io_bytes: usize) -> process::Process {
+        let mut proc = test_process(1);
+        proc.set_process_uid(String::from(process_uid));
+        proc.set_stat(process_stat_with(cpu_nanos, io_bytes));
+        proc
+    }
+
+    #[test]
+    fn diff_reports_appeared_disappeared_and_changed_processes() {
+        setting::install_test_config();
+
+        let mut prev = TotalStat::new();
+        let mut prev_container = ContainerStat::new(String::from("c1"));
+        prev_container.processes.push(process_with("gone", 100, 0));
+        prev_container.processes.push(process_with("stable", 100, 0));
+        prev_container.processes.push(process_with("busy", 100, 0));
+        prev.container_stats.push(prev_container);
+
+        let mut curr = TotalStat::new();
+        let mut curr_container = ContainerStat::new(String::from("c1"));
+        curr_container.processes.push(process_with("stable", 100, 0));
+        curr_container.processes.push(process_with("busy", 400, 0));
+        curr_container.processes.push(process_with("new", 10, 0));
+        curr.container_stats.push(curr_container);
+
+        let diff = curr.diff(&prev);
+
+        assert_eq!(diff.appeared, ["new"]);
+        assert_eq!(diff.disappeared, ["gone"]);
+        // the unchanged process stays out of the report
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].process_uid, "busy");
+        assert_eq!(
+            diff.changed[0].total_cpu_time_delta,
+            TimeCount::from_nanosecs(300)
+        );
+    }
+
+    #[test]
+    fn host_aggregate_sums_all_containers() {
+        let mut first = ContainerStat::new(String::from("c1"));
+        first.aggregated_stat = Some(process_stat_with(100, 10));
+        let mut second = ContainerStat::new(String::from("c2"));
+        second.aggregated_stat = Some(process_stat_with(250, 32));
+
+        let host_stat = host_aggregate_stat(&[first, second]);
+
+        assert_eq!(host_stat.get_total_cpu_time(), TimeCount::from_nanosecs(350));
+        assert_eq!(host_stat.get_total_io_read(), DataCount::from_byte(42));
+    }
+
+    // a sink that just records what the loop hands it
+    struct MockSink {
+        samples_begun: usize,
+        published: Vec<String>,
+    }
+
+    impl OutputSink for MockSink {
+        fn begin_sample(&mut self) {
+            self.samples_begun += 1;
+        }
+
+        fn publish(&mut self, chunk: &MessageChunk) -> Result<(), SinkError> {
+            self.published.push(chunk.message.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mock_sink_receives_every_chunk_of_a_sample() {
+        let chunks = chunk_payload(String::from("abcdefgh"), Some(3));
+        let mut sink = MockSink {
+            samples_begun: 0,
+            published: Vec::new(),
+        };
+
+        sink.begin_sample();
+        for message in &chunks {
+            let msg_chunk = MessageChunk::new(
+                String::from("sensor-1"),
+                String::from("cluster-1"),
+                message.clone(),
+                setting::PayloadCompression::None,
+            );
+            sink.publish(&msg_chunk).unwrap();
+        }
+
+        assert_eq!(sink.samples_begun, 1);
+        assert_eq!(sink.published, ["abc", "def", "gh"]);
+        // the chunks reassemble into the original payload
+        assert_eq!(sink.published.concat(), "abcdefgh");
+    }
+
+    #[test]
+    fn docker_top_pid_column_follows_the_header() {
+        // the default ps format puts pid second
+        assert_eq!(
+            docker_top_pid_column(Some("UID PID PPID C STIME TTY TIME CMD")),
+            1
+        );
+        // custom ps formats can move it anywhere, matched case-insensitively
+        assert_eq!(docker_top_pid_column(Some("PID USER TIME COMMAND")), 0);
+        assert_eq!(docker_top_pid_column(Some("USER TIME pid COMMAND")), 2);
+        // no header (or no pid column) keeps the historical index
+        assert_eq!(docker_top_pid_column(Some("USER TIME COMMAND")), 1);
+        assert_eq!(docker_top_pid_column(None), 1);
+    }
+
+    #[test]
+    fn a_disappeared_process_gets_exactly_one_tombstone() {
+        setting::install_test_config();
+
+        let mut prev = HashMap::new();
+        prev.insert(String::from("gone"), process_stat_with(1_000_000, 0));
+        prev.insert(String::from("stays"), process_stat_with(2_000_000, 0));
+        let mut curr = HashMap::new();
+        curr.insert(String::from("stays"), process_stat_with(3_000_000, 0));
+
+        let tombstones = tombstones_for(&prev, &curr);
+
+        // only the identity that vanished is tombstoned, exactly once
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!(tombstones[0].process_uid, "gone");
+        assert!(tombstones[0].terminated);
+        assert_eq!(tombstones[0].exit_code, None);
+        assert_eq!(
+            tombstones[0].last_total_cpu_time,
+            prev["gone"].get_total_cpu_time()
+        );
+    }
+
+    // a sink whose transport always rejects the chunk
+    struct RejectingSink;
+
+    impl OutputSink for RejectingSink {
+        fn publish(&mut self, _chunk: &MessageChunk) -> Result<(), SinkError> {
+            Err(SinkError::IOErr(io::Error::new(
+                io::ErrorKind::Other,
+                "broker rejected the chunk",
+            )))
+        }
+    }
+
+    #[test]
+    fn unpublishable_chunks_land_in_the_dead_letter_file() {
+        let path = std::env::temp_dir().join("virtual-sensor-test-dead-letter.jsonl");
+        let _ = fs::remove_file(&path);
+        let path = path.to_str().unwrap().to_owned();
+
+        let chunk = MessageChunk::new(
+            String::from("sensor-1"),
+            String::from("cluster-1"),
+            String::from("payload"),
+            setting::PayloadCompression::None,
+        );
+        let err = RejectingSink.publish(&chunk).unwrap_err();
+        append_dead_letter(&path, &chunk, &err).unwrap();
+
+        // the chunk is on disk together with why it could not be published
+        let line = fs::read_to_string(&path).unwrap();
+        let record: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert!(record["error"]
+            .as_str()
+            .unwrap()
+            .contains("broker rejected the chunk"));
+        assert_eq!(record["chunk"]["message"], "payload");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn flat_mode_emits_one_record_per_process() {
+        setting::install_test_config();
+
+        let mut total_stat = TotalStat::new();
+        let mut first = ContainerStat::new(String::from("c1"));
+        first.processes.push(test_process(101));
+        first.processes.push(test_process(102));
+        let mut second = ContainerStat::new(String::from("c2"));
+        second.processes.push(test_process(201));
+        total_stat.container_stats.push(first);
+        total_stat.container_stats.push(second);
+
+        let records = flat_records(&total_stat, setting::PayloadCompression::None);
+
+        // one standalone record per process, with its container embedded
+        assert_eq!(records.len(), 3);
+        let record: serde_json::Value = serde_json::from_str(&records[0]).unwrap();
+        assert_eq!(record["container_name"], "c1");
+    }
+
+    #[test]
+    fn line_protocol_tags_escape_separators() {
+        assert_eq!(escape_line_protocol_tag("plain"), "plain");
+        assert_eq!(
+            escape_line_protocol_tag("a b,c=d\\e"),
+            "a\\ b\\,c\\=d\\\\e"
+        );
+    }
+
+    #[test]
+    fn delay_to_next_boundary_aligns_to_the_interval() {
+        let interval = Duration::from_secs(10);
+
+        // 3s into an interval leaves 7s until the boundary
+        assert_eq!(
+            delay_to_next_boundary(Duration::from_secs(123), interval),
+            Duration::from_secs(7)
+        );
+        // exactly on a boundary means no wait
+        assert_eq!(
+            delay_to_next_boundary(Duration::from_secs(120), interval),
+            Duration::from_secs(0)
+        );
+        // a zero interval can't align and must not divide by zero
+        assert_eq!(
+            delay_to_next_boundary(Duration::from_secs(123), Duration::from_secs(0)),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn target_rotation_cycles_through_capped_targets() {
+        let targets: Vec<setting::MonitorTarget> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                serde_json::from_value(serde_json::json!({
+                    "container_name": name,
+                    "pid_list": [],
+                }))
+                .unwrap()
+            })
+            .collect();
+
+        let mut rotation = TargetRotation::new();
+
+        let first: Vec<&str> = rotation
+            .select(&targets, Some(2))
+            .iter()
+            .map(|target| target.container_name.as_str())
+            .collect();
+        let second: Vec<&str> = rotation
+            .select(&targets, Some(2))
+            .iter()
+            .map(|target| target.container_name.as_str())
+            .collect();
+
+        assert_eq!(first, ["a", "b"]);
+        // the cursor continues where the previous sample stopped
+        assert_eq!(second, ["c", "a"]);
+
+        // an over-sized cap degenerates to sampling everything
+        assert_eq!(rotation.select(&targets, Some(9)).len(), 3);
+    }
+
+    #[test]
+    fn config_ack_payload_reports_the_update_outcome() {
+        let ok_ack = config_ack_payload("00c0ffee00c0ffee", &Ok(()));
+        assert_eq!(
+            ok_ack,
+            serde_json::json!({
+                "config_version": "00c0ffee00c0ffee",
+                "success": true,
+            })
+        );
+
+        // a malformed payload produces a failure ack instead of a panic
+        let err_ack = config_ack_payload(
+            "00c0ffee00c0ffee",
+            &Err(ConfigError::IncorrectConfig),
+        );
+        assert_eq!(err_ack["config_version"], "00c0ffee00c0ffee");
+        assert_eq!(err_ack["success"], false);
+        assert!(!err_ack["error"].as_str().unwrap().is_empty());
+    }
+}
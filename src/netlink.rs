@@ -4,6 +4,7 @@ use netlink_sys::{protocols, Socket, SocketAddr};
 use std::convert::{From, Into, TryFrom, TryInto};
 use std::error;
 use std::io;
+use std::os::unix::io::AsRawFd;
 use std::{fmt, mem, slice};
 
 use crate::common;
@@ -524,7 +525,18 @@ impl NetlinkConnection {
     const BUFFER_SIZE: usize = 1024;
 
     pub fn new(protocol: NetlinkProtocol) -> Result<Self, NetlinkError> {
-        let mut socket = Socket::new(protocols::NETLINK_GENERIC)?;
+        let mut socket = Socket::new(protocols::NETLINK_GENERIC)
+            .map_err(|error| NetlinkError::SocketCreateErr(error.raw_os_error().unwrap_or(0)))?;
+
+        // grow the kernel-side receive buffer before any traffic flows, so
+        // batched queries on many-thread hosts don't drop responses
+        if let Some(rcvbuf_bytes) = crate::setting::get_glob_conf()
+            .map(|conf| conf.read().unwrap().get_netlink_rcvbuf_bytes())
+            .unwrap_or(None)
+        {
+            set_rcvbuf_size(&socket, rcvbuf_bytes);
+        }
+
         let self_addr = socket.bind_auto()?;
 
         Ok(Self {
@@ -552,10 +564,52 @@ impl NetlinkConnection {
     }
 }
 
+// so_rcvbufforce ignores rmem_max but needs cap_net_admin; fall back to
+// the capped so_rcvbuf and log what the kernel actually granted (it
+// reports double the requested size to account for bookkeeping overhead)
+fn set_rcvbuf_size(socket: &Socket, rcvbuf_bytes: usize) {
+    let fd = socket.as_raw_fd();
+    let value = rcvbuf_bytes as libc::c_int;
+    let value_ptr = &value as *const libc::c_int as *const libc::c_void;
+    let value_len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    let forced = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUFFORCE,
+            value_ptr,
+            value_len,
+        )
+    };
+    if forced != 0 {
+        unsafe { libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, value_ptr, value_len) };
+    }
+
+    let mut effective: libc::c_int = 0;
+    let mut effective_len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let read_back = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &mut effective as *mut libc::c_int as *mut libc::c_void,
+            &mut effective_len,
+        )
+    };
+    if read_back == 0 {
+        println!(
+            "netlink socket receive buffer: {} bytes effective (requested {})",
+            effective, rcvbuf_bytes
+        );
+    }
+}
+
 #[derive(Debug)]
 pub enum NetlinkError {
     IOErr(io::Error),
     GenericErr(Box<GenericError>),
+    SocketCreateErr(i32),
     MsgHeaderErr,
     AttrHeaderErr,
     UnknownMsgFlags(u16),
@@ -570,6 +624,10 @@ impl fmt::Display for NetlinkError {
         let result = match self {
             Self::IOErr(error) => String::from(format!("IO error: {}", error)),
             Self::GenericErr(error) => String::from(format!("Generic netlink error: {}", error)),
+            Self::SocketCreateErr(errno) => String::from(format!(
+                "Can't create AF_NETLINK socket (errno {}), check seccomp/namespace restrictions",
+                errno
+            )),
             Self::MsgHeaderErr => String::from(format!("Message header error")),
             Self::AttrHeaderErr => String::from(format!("Attribute header error")),
             Self::UnknownMsgFlags(flags) => {
@@ -620,9 +620,15 @@ pub struct GenericNetlinkConnection {
 
 impl GenericNetlinkConnection {
     pub fn new() -> Result<Self, GenericError> {
-        Ok(Self {
-            netlink_conn: NetlinkConnection::new(NetlinkProtocol::Generic)?,
-        })
+        let netlink_conn = match NetlinkConnection::new(NetlinkProtocol::Generic) {
+            Ok(conn) => conn,
+            Err(NetlinkError::SocketCreateErr(errno)) => {
+                return Err(GenericError::SocketCreateErr(errno))
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Self { netlink_conn })
     }
 
     pub fn send(&self, message: GenericNetlinkMessage) -> Result<(), GenericError> {
@@ -649,6 +655,7 @@ impl GenericNetlinkConnection {
 #[derive(Debug)]
 pub enum GenericError {
     NetlinkErr(NetlinkError),
+    SocketCreateErr(i32),
     HeaderErr(Vec<u8>),
     ControlMsgErr(GenericNetlinkMessage),
     UnknownControlCommand(GenericNetlinkMessageCommand),
@@ -666,6 +673,10 @@ impl fmt::Display for GenericError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let result = match self {
             Self::NetlinkErr(error) => String::from(format!("Netlink error: {}", error)),
+            Self::SocketCreateErr(errno) => String::from(format!(
+                "Can't create netlink socket (errno {}), taskstats is unavailable, check seccomp/namespace restrictions",
+                errno
+            )),
             Self::HeaderErr(buf) => String::from(format!("Header error: {:?}", buf)),
             Self::ControlMsgErr(generic_netlink_msg) => String::from(format!(
                 "Control message error: {:?}",
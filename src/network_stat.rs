@@ -1,34 +1,66 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "network-capture")]
+use std::collections::VecDeque;
+#[cfg(feature = "network-capture")]
 use std::convert::TryInto;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::IpAddr;
+#[cfg(feature = "network-capture")]
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::ops::{Add, AddAssign};
-use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::mpsc;
+#[cfg(feature = "network-capture")]
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+#[cfg(feature = "network-capture")]
 use std::sync::{Arc, Mutex};
+#[cfg(feature = "network-capture")]
 use std::thread;
-use std::{fmt, fs, io};
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "network-capture")]
+use std::fs;
+use std::{fmt, io};
 
+#[cfg(feature = "network-capture")]
 use pcap::{Capture, Device, Packet, Precision};
 use serde::{Serialize, Serializer};
 
-use crate::common::{self, CommonError, Count, DataCount, Endian, Inode};
-use crate::setting::{self, ConfigError};
+#[cfg(feature = "network-capture")]
+use crate::common::{self, Endian};
+use crate::common::{CommonError, Count, DataCount, Inode};
+#[cfg(feature = "network-capture")]
+use crate::setting;
+use crate::setting::ConfigError;
 use crate::setting::{
     has_irawstat_description, has_irawstat_iname, has_irawstat_uni_connection_stats,
 };
 
+#[cfg(feature = "network-capture")]
 const TCP_PAYLOAD_TYPE: u8 = 0x06;
+#[cfg(feature = "network-capture")]
 const UDP_PAYLOAD_TYPE: u8 = 0x11;
 
+#[cfg(feature = "network-capture")]
 const NULL_IPV4: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+#[cfg(feature = "network-capture")]
 const NULL_IPV6: IpAddr = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0));
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize)]
+// only the capture path constructs the variants; without it the types are
+// still part of Connection's (never-built) shape, so just quiet dead code
+#[cfg_attr(not(feature = "network-capture"), allow(unused))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
 pub enum ConnectionType {
     TCP,
     UDP,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize)]
+// which /proc/net table family a connection came from
+#[cfg_attr(not(feature = "network-capture"), allow(unused))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+pub enum AddressFamily {
+    Inet,
+    Inet6,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
 pub struct UniConnection {
     src_addr: IpAddr,
     src_port: u16,
@@ -55,16 +87,18 @@ impl UniConnection {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
 pub struct Connection {
     local_addr: IpAddr,
     local_port: u16,
     remote_addr: IpAddr,
     remote_port: u16,
     conn_type: ConnectionType,
+    family: AddressFamily,
 }
 
 impl Connection {
+    #[cfg(feature = "network-capture")]
     pub fn new(
         local_addr: IpAddr,
         local_port: u16,
@@ -72,12 +106,19 @@ impl Connection {
         remote_port: u16,
         conn_type: ConnectionType,
     ) -> Self {
+        // the family follows the address, the v4/v6 tables never mix them
+        let family = match local_addr {
+            IpAddr::V4(_) => AddressFamily::Inet,
+            IpAddr::V6(_) => AddressFamily::Inet6,
+        };
+
         Self {
             local_addr,
             local_port,
             remote_addr,
             remote_port,
             conn_type,
+            family,
         }
     }
 
@@ -100,6 +141,19 @@ impl Connection {
     pub fn get_connection_type(&self) -> ConnectionType {
         self.conn_type
     }
+
+    #[cfg(feature = "network-capture")]
+    pub fn get_family(&self) -> AddressFamily {
+        self.family
+    }
+}
+
+// wall-clock seconds, the granularity the connection ttl works at
+fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize)]
@@ -110,8 +164,21 @@ pub struct UniConnectionStat {
     total_data_count: DataCount,
     real_data_count: DataCount,
 
+    // how often this connection's counters went backwards between samples
+    reset_count: Count,
+
     #[serde(skip_serializing)]
     is_used: bool,
+
+    // unix seconds of the last packet merged into this entry, drives the
+    // idle-connection ttl in remove_used_uni_conn_stats
+    #[serde(skip_serializing)]
+    last_activity_unix_secs: u64,
+
+    // unix seconds this connection first appeared, kept stable across
+    // samples by the control thread's first-seen registry
+    #[serde(skip_serializing)]
+    first_seen_unix_secs: u64,
 }
 
 #[allow(unused)]
@@ -124,7 +191,12 @@ impl UniConnectionStat {
             total_data_count: DataCount::from_byte(0),
             real_data_count: DataCount::from_byte(0),
 
+            reset_count: Count::new(0),
+
             is_used: false,
+
+            last_activity_unix_secs: now_unix_secs(),
+            first_seen_unix_secs: now_unix_secs(),
         }
     }
 
@@ -144,6 +216,14 @@ impl UniConnectionStat {
         self.real_data_count
     }
 
+    pub fn get_first_seen_unix_secs(&self) -> u64 {
+        self.first_seen_unix_secs
+    }
+
+    pub fn get_reset_count(&self) -> Count {
+        self.reset_count
+    }
+
     fn mark_as_used(&mut self) {
         self.is_used = true;
     }
@@ -165,7 +245,14 @@ impl Add<Self> for UniConnectionStat {
             total_data_count: self.total_data_count + other.total_data_count,
             real_data_count: self.real_data_count + other.real_data_count,
 
+            reset_count: self.reset_count + other.reset_count,
+
             is_used: false,
+
+            last_activity_unix_secs: self
+                .last_activity_unix_secs
+                .max(other.last_activity_unix_secs),
+            first_seen_unix_secs: self.first_seen_unix_secs.min(other.first_seen_unix_secs),
         }
     }
 }
@@ -180,13 +267,21 @@ impl AddAssign<Self> for UniConnectionStat {
         self.packet_count += other.packet_count;
         self.total_data_count += other.total_data_count;
         self.real_data_count += other.real_data_count;
+        self.last_activity_unix_secs = self
+            .last_activity_unix_secs
+            .max(other.last_activity_unix_secs);
+        self.first_seen_unix_secs = self.first_seen_unix_secs.min(other.first_seen_unix_secs);
     }
 }
 
+#[cfg(feature = "network-capture")]
 #[derive(Debug)]
 struct ThreadData {
     device: Device,
     uni_conn_stats: Option<HashMap<UniConnection, UniConnectionStat>>,
+
+    // insertion order of the live table, oldest first, for fifo eviction
+    insertion_order: VecDeque<UniConnection>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -202,17 +297,43 @@ pub struct InterfaceRawStat {
         skip_serializing_if = "has_irawstat_uni_connection_stats"
     )]
     uni_connection_stats: HashMap<UniConnection, UniConnectionStat>,
+
+    // current size of the live connection table, a gauge for churn monitoring
+    connection_table_size: usize,
+
+    // link-layer type and mtu from sysfs, for interpreting total vs real data;
+    // None when disabled or the interface disappeared between samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link_type: Option<u16>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtu: Option<u32>,
 }
 
 impl InterfaceRawStat {
+    #[cfg(feature = "network-capture")]
     pub fn new(iname: String, description: String) -> Self {
         Self {
             iname,
             description,
             uni_connection_stats: HashMap::new(),
+            connection_table_size: 0,
+            link_type: None,
+            mtu: None,
         }
     }
 
+    // best-effort sysfs reads, a vanished interface just leaves both unset
+    #[cfg(feature = "network-capture")]
+    pub fn read_link_info(&mut self) {
+        self.link_type = fs::read_to_string(format!("/sys/class/net/{}/type", self.iname))
+            .ok()
+            .and_then(|value| value.trim().parse().ok());
+        self.mtu = fs::read_to_string(format!("/sys/class/net/{}/mtu", self.iname))
+            .ok()
+            .and_then(|value| value.trim().parse().ok());
+    }
+
     pub fn get_uni_connection_stat(
         &mut self,
         uni_conn: &UniConnection,
@@ -224,16 +345,55 @@ impl InterfaceRawStat {
     }
 
     pub fn remove_used_uni_conn_stats(&mut self) {
+        let ttl_secs = crate::setting::get_glob_conf()
+            .map(|conf| conf.read().unwrap().get_connection_ttl_secs())
+            .unwrap_or(None);
+        let now = now_unix_secs();
+
+        // used entries were attributed this sample and drop as before; idle
+        // unused entries additionally expire once past the configured ttl
         self.uni_connection_stats
-            .retain(|_uni_conn, uni_conn_stat| !uni_conn_stat.is_used);
+            .retain(|_uni_conn, uni_conn_stat| {
+                if uni_conn_stat.is_used {
+                    return false;
+                }
+
+                match ttl_secs {
+                    Some(ttl) => {
+                        now.saturating_sub(uni_conn_stat.last_activity_unix_secs) <= ttl
+                    }
+                    None => true,
+                }
+            });
     }
 }
 
+// hashmap iteration order is nondeterministic, sort by the connection tuple
+// so identical samples serialize byte-identically
 fn get_irawstat_uni_conn_stats_serialize<S: Serializer>(
     input: &HashMap<UniConnection, UniConnectionStat>,
     serializer: S,
 ) -> Result<S::Ok, S::Error> {
-    serializer.collect_seq(input.values())
+    let mut entries: Vec<(&UniConnection, &UniConnectionStat)> = input.iter().collect();
+    entries.sort_by_key(|(uni_conn, _)| **uni_conn);
+    serializer.collect_seq(entries.into_iter().map(|(_, uni_conn_stat)| uni_conn_stat))
+}
+
+// interface-wide counters from /proc/net/dev, the ground-truth totals that
+// also cover traffic not attributed to any monitored process
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceTotals {
+    iname: String,
+
+    rx_bytes: DataCount,
+    rx_packets: Count,
+    rx_errs: Count,
+    rx_drops: Count,
+
+    tx_bytes: DataCount,
+    tx_packets: Count,
+    tx_errs: Count,
+    tx_drops: Count,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -246,6 +406,38 @@ pub struct NetworkRawStat {
 
     #[serde(serialize_with = "get_network_rawstat_uni_connection_stats_serialize")]
     interface_rawstats: HashMap<String, InterfaceRawStat>,
+
+    #[serde(
+        serialize_with = "get_network_rawstat_interface_totals_serialize",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    interface_totals: HashMap<String, InterfaceTotals>,
+
+    // tcp sockets in LISTEN state, keyed by inode so a process's fd scan can
+    // resolve the ports it listens on
+    #[serde(skip_serializing)]
+    listening_lookup_table: HashMap<Inode, u16>,
+
+    // sockets of protocols the sensor doesn't attribute (sctp, udplite,
+    // raw); known so a process's fd scan can skip them cleanly and count
+    // them instead of treating them as unmatched tcp/udp
+    #[serde(skip_serializing)]
+    unsupported_protocol_inodes: HashSet<Inode>,
+
+    // inode → owning pid claims within this sample, so a socket inherited
+    // across a fork family is attributed to exactly one process
+    #[serde(skip_serializing)]
+    inode_claims: HashMap<Inode, crate::process::Pid>,
+
+    // inode → precomputed attribution record, built once per sample so
+    // process collection resolves each socket with a single map lookup
+    #[serde(skip_serializing)]
+    conn_stat_index: HashMap<Inode, crate::process::ConnectionIndexEntry>,
+
+    // remote address → hostname from best-effort reverse dns; only remotes
+    // whose lookup had finished by this sample appear
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    remote_hostnames: HashMap<IpAddr, String>,
 }
 
 impl NetworkRawStat {
@@ -254,7 +446,41 @@ impl NetworkRawStat {
             conn_lookup_table: HashMap::new(),
             iname_lookup_table: HashMap::new(),
             interface_rawstats: HashMap::new(),
+            interface_totals: HashMap::new(),
+            listening_lookup_table: HashMap::new(),
+            unsupported_protocol_inodes: HashSet::new(),
+            inode_claims: HashMap::new(),
+            conn_stat_index: HashMap::new(),
+            remote_hostnames: HashMap::new(),
+        }
+    }
+
+    pub fn attach_remote_hostname(&mut self, addr: IpAddr, hostname: String) {
+        self.remote_hostnames.insert(addr, hostname);
+    }
+
+    // claim an inode for a process; the first claimant wins and stays the
+    // owner for the rest of the sample, repeat claims by the owner are fine
+    pub fn claim_inode(&mut self, inode: &Inode, real_pid: crate::process::Pid) -> bool {
+        *self.inode_claims.entry(*inode).or_insert(real_pid) == real_pid
+    }
+
+    // inodes are unique within a namespace, but guard against a cross-family
+    // clash so a tcp6 entry can't silently replace a tcp one
+    #[cfg(feature = "network-capture")]
+    pub fn insert_connection(&mut self, inode: Inode, connection: Connection) {
+        if let Some(existing) = self.conn_lookup_table.get(&inode) {
+            if existing.get_family() != connection.get_family() {
+                println!(
+                    "warning: inode {:?} maps to connections in multiple families, keeping {:?}",
+                    inode,
+                    existing.get_family()
+                );
+                return;
+            }
         }
+
+        self.conn_lookup_table.insert(inode, connection);
     }
 
     pub fn lookup_connection(&self, inode: &Inode) -> Option<&Connection> {
@@ -263,12 +489,91 @@ impl NetworkRawStat {
             .and_then(|connection| Some(connection))
     }
 
+    pub fn lookup_listening_port(&self, inode: &Inode) -> Option<u16> {
+        self.listening_lookup_table.get(inode).copied()
+    }
+
+    pub fn is_unsupported_protocol(&self, inode: &Inode) -> bool {
+        self.unsupported_protocol_inodes.contains(inode)
+    }
+
+    // record the inodes of sockets from the protocol tables we don't parse
+    // for connections; the files are absent on kernels without the protocol
+    #[cfg(feature = "network-capture")]
+    fn collect_unsupported_protocol_inodes(&mut self) {
+        // udplite and raw share the udp table layout with the inode in
+        // column 9; the sctp tables carry it in their own columns
+        let tables: [(&str, usize); 6] = [
+            ("/proc/net/udplite", 9),
+            ("/proc/net/udplite6", 9),
+            ("/proc/net/raw", 9),
+            ("/proc/net/raw6", 9),
+            ("/proc/net/sctp/eps", 7),
+            ("/proc/net/sctp/assocs", 10),
+        ];
+
+        for (path, inode_index) in tables.iter() {
+            let content = match fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+
+            for line in content.lines().skip(1) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if let Some(inode) = fields.get(*inode_index).and_then(|field| field.parse().ok())
+                {
+                    self.unsupported_protocol_inodes.insert(Inode::new(inode));
+                }
+            }
+        }
+    }
+
+    pub fn connection_entries(&self) -> impl Iterator<Item = (&Inode, &Connection)> {
+        self.conn_lookup_table.iter()
+    }
+
+    // read a uniconnection stat without marking it used; index building
+    // must not consume stats of connections never attributed this sample
+    pub fn peek_uni_connection_stat(
+        &self,
+        iname: &str,
+        uni_conn: &UniConnection,
+    ) -> Option<&UniConnectionStat> {
+        self.interface_rawstats
+            .get(iname)?
+            .uni_connection_stats
+            .get(uni_conn)
+    }
+
+    pub fn set_connection_stat_index(
+        &mut self,
+        index: HashMap<Inode, crate::process::ConnectionIndexEntry>,
+    ) {
+        self.conn_stat_index = index;
+    }
+
+    // hand out a prebuilt attribution record and mark its uniconnection
+    // stats used, exactly as the per-inode path did on attribution
+    pub fn take_connection_stat(
+        &mut self,
+        inode: &Inode,
+    ) -> Option<crate::process::ConnectionIndexEntry> {
+        let entry = self.conn_stat_index.remove(inode)?;
+        if let Some(irawstat) = self.interface_rawstats.get_mut(entry.interface_name()) {
+            let (uni_conn, reverse_uni_conn) = entry.uni_connections();
+            irawstat.get_uni_connection_stat(&uni_conn);
+            irawstat.get_uni_connection_stat(&reverse_uni_conn);
+        }
+        Some(entry)
+    }
+
     pub fn lookup_interface_name(&self, connection: &Connection) -> Option<&str> {
         self.iname_lookup_table
             .get(connection)
             .and_then(|name| Some(name.as_str()))
     }
 
+    #[allow(unused)]
     pub fn get_irawstat(&mut self, iname: &str) -> Option<&mut InterfaceRawStat> {
         self.interface_rawstats
             .get_mut(iname)
@@ -282,13 +587,66 @@ impl NetworkRawStat {
     }
 }
 
+// sorted by interface name for deterministic output
 fn get_network_rawstat_uni_connection_stats_serialize<S: Serializer>(
     input: &HashMap<String, InterfaceRawStat>,
     serializer: S,
 ) -> Result<S::Ok, S::Error> {
-    serializer.collect_seq(input.values())
+    let mut entries: Vec<(&String, &InterfaceRawStat)> = input.iter().collect();
+    entries.sort_by_key(|(iname, _)| *iname);
+    serializer.collect_seq(entries.into_iter().map(|(_, irawstat)| irawstat))
+}
+
+// sorted by interface name for deterministic output
+fn get_network_rawstat_interface_totals_serialize<S: Serializer>(
+    input: &HashMap<String, InterfaceTotals>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries: Vec<(&String, &InterfaceTotals)> = input.iter().collect();
+    entries.sort_by_key(|(iname, _)| *iname);
+    serializer.collect_seq(entries.into_iter().map(|(_, totals)| totals))
+}
+
+// parse /proc/net/dev into per-interface totals; the first two lines are
+// headers, then one "<iname>: <16 counters>" line per interface
+#[cfg(feature = "network-capture")]
+fn get_interface_totals() -> Result<HashMap<String, InterfaceTotals>, NetworkStatError> {
+    let file_content = fs::read_to_string("/proc/net/dev")?;
+    let mut interface_totals = HashMap::new();
+
+    for line in file_content.lines().skip(2) {
+        let (iname, counters) = match line.split_once(':') {
+            Some((iname, counters)) => (iname.trim(), counters),
+            None => continue,
+        };
+
+        let counters: Vec<&str> = counters.split_whitespace().collect();
+        if counters.len() < 12 {
+            continue;
+        }
+
+        interface_totals.insert(
+            iname.to_string(),
+            InterfaceTotals {
+                iname: iname.to_string(),
+
+                rx_bytes: DataCount::from_byte(counters[0].parse()?),
+                rx_packets: Count::new(counters[1].parse()?),
+                rx_errs: Count::new(counters[2].parse()?),
+                rx_drops: Count::new(counters[3].parse()?),
+
+                tx_bytes: DataCount::from_byte(counters[8].parse()?),
+                tx_packets: Count::new(counters[9].parse()?),
+                tx_errs: Count::new(counters[10].parse()?),
+                tx_drops: Count::new(counters[11].parse()?),
+            },
+        );
+    }
+
+    Ok(interface_totals)
 }
 
+#[cfg(feature = "network-capture")]
 fn parse_ipv4_packet(data: &[u8]) -> Result<UniConnectionStat, NetworkStatError> {
     const IPV4_FIXED_HEADER_SIZE: usize = 20;
 
@@ -346,10 +704,16 @@ fn parse_ipv4_packet(data: &[u8]) -> Result<UniConnectionStat, NetworkStatError>
         total_data_count: DataCount::from_byte(0),
         real_data_count: DataCount::from_byte(payload_length),
 
+        reset_count: Count::new(0),
+
         is_used: false,
+
+        last_activity_unix_secs: now_unix_secs(),
+        first_seen_unix_secs: now_unix_secs(),
     })
 }
 
+#[cfg(feature = "network-capture")]
 fn parse_ipv6_packet(data: &[u8]) -> Result<UniConnectionStat, NetworkStatError> {
     const IPV6_FIXED_HEADER_SIZE: usize = 40;
 
@@ -442,10 +806,16 @@ fn parse_ipv6_packet(data: &[u8]) -> Result<UniConnectionStat, NetworkStatError>
         total_data_count: DataCount::from_byte(0),
         real_data_count: DataCount::from_byte(payload_length - (curr_idx - IPV6_FIXED_HEADER_SIZE)),
 
+        reset_count: Count::new(0),
+
         is_used: false,
+
+        last_activity_unix_secs: now_unix_secs(),
+        first_seen_unix_secs: now_unix_secs(),
     })
 }
 
+#[cfg(feature = "network-capture")]
 fn get_uni_conn_stat(packet: Packet) -> Result<UniConnectionStat, NetworkStatError> {
     let data = packet.data;
 
@@ -485,6 +855,48 @@ fn get_uni_conn_stat(packet: Packet) -> Result<UniConnectionStat, NetworkStatErr
     })
 }
 
+// fifo-evict the oldest connection entries until the table fits the cap,
+// bounding memory on high-connection-churn hosts
+#[cfg(feature = "network-capture")]
+fn evict_oldest_connections(
+    uni_conn_stats: &mut HashMap<UniConnection, UniConnectionStat>,
+    insertion_order: &mut VecDeque<UniConnection>,
+    cap: usize,
+) {
+    while uni_conn_stats.len() > cap {
+        match insertion_order.pop_front() {
+            Some(oldest) => {
+                uni_conn_stats.remove(&oldest);
+            }
+            None => break,
+        }
+    }
+}
+
+// track one connection's cumulative counter across samples, bumping its
+// reset count whenever the counter goes backwards (interface down/up, a
+// connection reestablished on the same tuple); returns the running total
+#[cfg(feature = "network-capture")]
+fn track_counter_reset(
+    prev_counters: &mut HashMap<UniConnection, DataCount>,
+    reset_counts: &mut HashMap<UniConnection, Count>,
+    uni_conn: UniConnection,
+    total_data_count: DataCount,
+) -> Count {
+    if let Some(prev) = prev_counters.get(&uni_conn) {
+        if total_data_count < *prev {
+            *reset_counts.entry(uni_conn).or_insert(Count::new(0)) += Count::new(1);
+        }
+    }
+    prev_counters.insert(uni_conn, total_data_count);
+
+    reset_counts
+        .get(&uni_conn)
+        .copied()
+        .unwrap_or(Count::new(0))
+}
+
+#[cfg(feature = "network-capture")]
 fn control_thread(
     ctrl_data_in_read_end: Receiver<()>,
     ctrl_data_in_write_end: Sender<NetworkRawStat>,
@@ -494,6 +906,18 @@ fn control_thread(
 
     let mut thread_data: HashMap<String, Arc<Mutex<ThreadData>>> = HashMap::new();
 
+    // previous counter values and accumulated resets, keyed by connection
+    let mut prev_counters: HashMap<UniConnection, DataCount> = HashMap::new();
+    let mut reset_counts: HashMap<UniConnection, Count> = HashMap::new();
+
+    // when each connection was first seen, never overwritten so re-created
+    // table entries keep their original timestamp
+    let mut first_seen: HashMap<UniConnection, u64> = HashMap::new();
+
+    // when each connection last appeared in a sample, used to age the three
+    // maps above out so they don't grow without bound on high-churn hosts
+    let mut last_touched: HashMap<UniConnection, u64> = HashMap::new();
+
     loop {
         // check if someone want to get data
         match ctrl_data_in_read_end.recv_timeout(
@@ -535,6 +959,14 @@ fn control_thread(
                     let remote_addr = IpAddr::V4(Ipv4Addr::new(f[0], f[1], f[2], f[3]));
                     let remote_port = u16::from_be_bytes(g[0..2].try_into().unwrap());
 
+                    // state 0A is LISTEN; those sockets never reach the
+                    // connection table but their ports matter for discovery
+                    if a[3] == "0A" {
+                        network_raw_stat
+                            .listening_lookup_table
+                            .insert(Inode::new(a[9].parse()?), local_port);
+                    }
+
                     if local_addr == NULL_IPV4 || remote_addr == NULL_IPV4 {
                         continue;
                     }
@@ -547,9 +979,7 @@ fn control_thread(
                         ConnectionType::TCP,
                     );
 
-                    network_raw_stat
-                        .conn_lookup_table
-                        .insert(Inode::new(a[9].parse()?), connection);
+                    network_raw_stat.insert_connection(Inode::new(a[9].parse()?), connection);
 
                     'outer1: for device in &devices {
                         for address in &device.addresses {
@@ -613,13 +1043,18 @@ fn control_thread(
                         ConnectionType::TCP,
                     );
 
+                    // same LISTEN handling as the ipv4 table
+                    if a[3] == "0A" {
+                        network_raw_stat
+                            .listening_lookup_table
+                            .insert(Inode::new(a[9].parse()?), local_port);
+                    }
+
                     if local_addr == NULL_IPV6 || remote_addr == NULL_IPV6 {
                         continue;
                     }
 
-                    network_raw_stat
-                        .conn_lookup_table
-                        .insert(Inode::new(a[9].parse()?), connection);
+                    network_raw_stat.insert_connection(Inode::new(a[9].parse()?), connection);
 
                     'outer2: for device in &devices {
                         for address in &device.addresses {
@@ -671,9 +1106,7 @@ fn control_thread(
                         continue;
                     }
 
-                    network_raw_stat
-                        .conn_lookup_table
-                        .insert(Inode::new(a[9].parse()?), connection);
+                    network_raw_stat.insert_connection(Inode::new(a[9].parse()?), connection);
 
                     'outer3: for device in &devices {
                         for address in &device.addresses {
@@ -741,9 +1174,7 @@ fn control_thread(
                         continue;
                     }
 
-                    network_raw_stat
-                        .conn_lookup_table
-                        .insert(Inode::new(a[9].parse()?), connection);
+                    network_raw_stat.insert_connection(Inode::new(a[9].parse()?), connection);
 
                     'outer4: for device in &devices {
                         for address in &device.addresses {
@@ -763,6 +1194,8 @@ fn control_thread(
                     }
                 }
 
+                network_raw_stat.collect_unsupported_protocol_inodes();
+
                 // build interface raw stats
                 for (iname, thread_data) in &thread_data {
                     let mut mutex_lock = thread_data.lock()?;
@@ -774,12 +1207,62 @@ fn control_thread(
 
                     irawstat.uni_connection_stats =
                         mutex_lock.uni_conn_stats.take().unwrap_or(HashMap::new());
+                    mutex_lock.insertion_order.clear();
+
+                    // gauge of the live table size before the unused entries
+                    // are pruned, to watch growth on high-churn hosts
+                    irawstat.connection_table_size = irawstat.uni_connection_stats.len();
+
+                    if setting::get_glob_conf()?.read()?.get_interface_link_info() {
+                        irawstat.read_link_info();
+                    }
+
+                    // a counter lower than the previous sample means the
+                    // underlying counter was reset, e.g. interface down/up
+                    for (uni_conn, uni_conn_stat) in &mut irawstat.uni_connection_stats {
+                        uni_conn_stat.reset_count = track_counter_reset(
+                            &mut prev_counters,
+                            &mut reset_counts,
+                            *uni_conn,
+                            uni_conn_stat.total_data_count,
+                        );
+
+                        uni_conn_stat.first_seen_unix_secs = *first_seen
+                            .entry(*uni_conn)
+                            .or_insert(uni_conn_stat.first_seen_unix_secs);
+
+                        last_touched.insert(*uni_conn, now_unix_secs());
+                    }
 
                     network_raw_stat
                         .interface_rawstats
                         .insert(iname.clone(), irawstat);
                 }
 
+                // age tracking state out with the connections themselves, so
+                // long-gone connections don't pin map entries forever
+                if let Some(ttl_secs) =
+                    setting::get_glob_conf()?.read()?.get_connection_ttl_secs()
+                {
+                    let now = now_unix_secs();
+                    last_touched.retain(|uni_conn, touched| {
+                        if now.saturating_sub(*touched) <= ttl_secs {
+                            return true;
+                        }
+                        prev_counters.remove(uni_conn);
+                        reset_counts.remove(uni_conn);
+                        first_seen.remove(uni_conn);
+                        false
+                    });
+                }
+
+                // interface-wide totals from /proc/net/dev as a ground-truth
+                // denominator next to the per-process attribution
+                match get_interface_totals() {
+                    Ok(interface_totals) => network_raw_stat.interface_totals = interface_totals,
+                    Err(err) => println!("warning: can't parse /proc/net/dev: {}", err),
+                }
+
                 // send networkRawStat out
                 ctrl_data_in_write_end.send(network_raw_stat)?;
             }
@@ -801,6 +1284,7 @@ fn control_thread(
                 let _thread_data = Arc::new(Mutex::new(ThreadData {
                     device: device.clone(),
                     uni_conn_stats: None,
+                    insertion_order: VecDeque::new(),
                 }));
 
                 thread_data.insert(iname, Arc::clone(&_thread_data));
@@ -812,11 +1296,29 @@ fn control_thread(
     }
 }
 
+// map a pcap open failure onto a capture init variant carrying a remediation hint
+#[cfg(feature = "network-capture")]
+fn classify_capture_init_error(pcap_err: pcap::Error) -> NetworkStatError {
+    let message = format!("{}", pcap_err);
+    let lowercase = message.to_lowercase();
+
+    if lowercase.contains("permission") || lowercase.contains("not permitted") {
+        NetworkStatError::CaptureInitPermissionErr(message)
+    } else if lowercase.contains("busy") || lowercase.contains("in use") {
+        NetworkStatError::CaptureInitDeviceBusy(message)
+    } else if lowercase.contains("not supported") || lowercase.contains("unsupported") {
+        NetworkStatError::CaptureInitUnsupported(message)
+    } else {
+        NetworkStatError::PcapErr(pcap_err)
+    }
+}
+
+#[cfg(feature = "network-capture")]
 fn capture_thread(thread_data: Arc<Mutex<ThreadData>>) -> Result<(), NetworkStatError> {
     // init capture
     let device = thread_data.lock()?.device.clone();
 
-    let mut capture = Capture::from_device(device)?
+    let capture = Capture::from_device(device)?
         .snaplen(
             setting::get_glob_conf()?
                 .read()?
@@ -833,7 +1335,16 @@ fn capture_thread(thread_data: Arc<Mutex<ThreadData>>) -> Result<(), NetworkStat
                 .unwrap(),
         )
         .precision(Precision::Nano)
-        .open()?;
+        .open();
+
+    let mut capture = match capture {
+        Ok(capture) => capture,
+        Err(pcap_err) => {
+            let init_err = classify_capture_init_error(pcap_err);
+            println!("warning: can't start capture: {}", init_err);
+            return Err(init_err);
+        }
+    };
 
     // main loop
     loop {
@@ -856,12 +1367,38 @@ fn capture_thread(thread_data: Arc<Mutex<ThreadData>>) -> Result<(), NetworkStat
                     Err(_) => continue,
                 };
 
+                let is_new_entry = !mutex_lock
+                    .uni_conn_stats
+                    .as_ref()
+                    .unwrap()
+                    .contains_key(&uni_conn_stat.uni_conn);
+
                 *mutex_lock
                     .uni_conn_stats
                     .as_mut()
                     .unwrap()
                     .entry(uni_conn_stat.uni_conn)
                     .or_insert(uni_conn_stat) += uni_conn_stat;
+
+                // bound the table on high-churn hosts, evicting oldest first.
+                // the table is handed off every sample, so nothing evicted here
+                // is referenced by the sample being built
+                if is_new_entry {
+                    mutex_lock.insertion_order.push_back(uni_conn_stat.uni_conn);
+
+                    let max_connection_entries = setting::get_glob_conf()?
+                        .read()?
+                        .get_max_connection_entries();
+
+                    if let Some(cap) = max_connection_entries {
+                        let thread_data = &mut *mutex_lock;
+                        evict_oldest_connections(
+                            thread_data.uni_conn_stats.as_mut().unwrap(),
+                            &mut thread_data.insertion_order,
+                            cap,
+                        );
+                    }
+                }
             }
             Err(pcap::Error::TimeoutExpired) => continue,
             Err(pcap_err) => return Err(NetworkStatError::PcapErr(pcap_err)),
@@ -869,12 +1406,33 @@ fn capture_thread(thread_data: Arc<Mutex<ThreadData>>) -> Result<(), NetworkStat
     }
 }
 
+#[cfg(feature = "network-capture")]
 lazy_static! {
     static ref CONTROL_DATA_IN_WRITE_END: Mutex<Option<Sender<()>>> = Mutex::new(None);
     static ref CONTROL_DATA_IN_READ_END: Mutex<Option<Receiver<NetworkRawStat>>> = Mutex::new(None);
 }
 
+// open one capture synchronously so permission and device problems surface
+// here instead of inside a detached capture thread; the probe capture is
+// dropped right away, the per-interface threads open their own
+#[cfg(feature = "network-capture")]
+fn probe_capture_open() -> Result<(), NetworkStatError> {
+    let device = match Device::list()?.into_iter().next() {
+        Some(device) => device,
+        // no interfaces to capture on is not an init failure
+        None => return Ok(()),
+    };
+
+    match Capture::from_device(device)?.open() {
+        Ok(_) => Ok(()),
+        Err(pcap_err) => Err(classify_capture_init_error(pcap_err)),
+    }
+}
+
+#[cfg(feature = "network-capture")]
 pub fn init_network_stat_capture() -> Result<(), NetworkStatError> {
+    probe_capture_open()?;
+
     let (_control_data_in_write_end, control_data_in_read_end) = mpsc::channel();
     let (control_data_out_write_end, _control_data_out_read_end) = mpsc::channel();
 
@@ -886,6 +1444,7 @@ pub fn init_network_stat_capture() -> Result<(), NetworkStatError> {
     Ok(())
 }
 
+#[cfg(feature = "network-capture")]
 pub fn get_network_rawstat() -> Result<NetworkRawStat, NetworkStatError> {
     // signal to control thread to get data
     CONTROL_DATA_IN_WRITE_END
@@ -898,13 +1457,30 @@ pub fn get_network_rawstat() -> Result<NetworkRawStat, NetworkStatError> {
     Ok(CONTROL_DATA_IN_READ_END.lock()?.as_ref().unwrap().recv()?)
 }
 
+// without the network-capture feature there is nothing to start and every
+// sample just carries an empty network rawstat; process collection is
+// unaffected and the sensor no longer needs CAP_NET_RAW
+#[cfg(not(feature = "network-capture"))]
+pub fn init_network_stat_capture() -> Result<(), NetworkStatError> {
+    Ok(())
+}
+
+#[cfg(not(feature = "network-capture"))]
+pub fn get_network_rawstat() -> Result<NetworkRawStat, NetworkStatError> {
+    Ok(NetworkRawStat::new())
+}
+
 #[derive(Debug)]
 pub enum NetworkStatError {
     ConvertErr,
     ChannelSendErr,
     ChannelRecvErr,
     ParseIntErr(std::num::ParseIntError),
+    #[cfg(feature = "network-capture")]
     PcapErr(pcap::Error),
+    CaptureInitPermissionErr(String),
+    CaptureInitDeviceBusy(String),
+    CaptureInitUnsupported(String),
     UnknownVLANTag(u16),
     UnknownProtocol(u16),
     Ipv4PacketLenErr(usize),
@@ -929,7 +1505,20 @@ impl fmt::Display for NetworkStatError {
             Self::ChannelRecvErr => String::from(format!("Channel recv error")),
             Self::ParseIntErr(error) => String::from(format!("Parse integer error: {}", error)),
 
+            #[cfg(feature = "network-capture")]
             Self::PcapErr(error) => String::from(format!("Pcap error: {}", error)),
+            Self::CaptureInitPermissionErr(message) => String::from(format!(
+                "Capture init failed: {}, run as root or grant CAP_NET_RAW/CAP_NET_ADMIN",
+                message
+            )),
+            Self::CaptureInitDeviceBusy(message) => String::from(format!(
+                "Capture init failed: {}, another capture may hold the device, close it or exclude the interface",
+                message
+            )),
+            Self::CaptureInitUnsupported(message) => String::from(format!(
+                "Capture init failed: {}, the device doesn't support live capture, exclude it",
+                message
+            )),
             Self::UnknownVLANTag(vlan_tag) => {
                 String::from(format!("Unknown vlan tag: {}", vlan_tag))
             }
@@ -965,6 +1554,7 @@ impl fmt::Display for NetworkStatError {
     }
 }
 
+#[cfg(feature = "network-capture")]
 impl From<pcap::Error> for NetworkStatError {
     fn from(error: pcap::Error) -> Self {
         Self::PcapErr(error)
@@ -1012,3 +1602,148 @@ impl From<ConfigError> for NetworkStatError {
         Self::ConfigErr(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::net::Ipv4Addr;
+
+    fn test_uni_conn() -> UniConnection {
+        UniConnection::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            1234,
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            80,
+            ConnectionType::TCP,
+        )
+    }
+
+    #[test]
+    fn uni_connection_stat_add_merges_counters_and_lifetimes() {
+        let mut left = UniConnectionStat::new(test_uni_conn());
+        left.packet_count = Count::new(3);
+        left.total_data_count = DataCount::from_byte(100);
+        left.first_seen_unix_secs = 50;
+        left.last_activity_unix_secs = 60;
+
+        let mut right = UniConnectionStat::new(test_uni_conn());
+        right.packet_count = Count::new(2);
+        right.total_data_count = DataCount::from_byte(40);
+        right.first_seen_unix_secs = 10;
+        right.last_activity_unix_secs = 90;
+
+        let merged = left + right;
+        assert_eq!(merged.packet_count, Count::new(5));
+        assert_eq!(merged.total_data_count, DataCount::from_byte(140));
+        // lifetime spans both operands: earliest first-seen, latest activity
+        assert_eq!(merged.first_seen_unix_secs, 10);
+        assert_eq!(merged.last_activity_unix_secs, 90);
+    }
+
+    #[test]
+    fn first_inode_claim_wins() {
+        let mut net_rawstat = NetworkRawStat::new();
+        let inode = Inode::try_from("4242").unwrap();
+
+        let owner = crate::process::Pid::new(10);
+        let intruder = crate::process::Pid::new(20);
+
+        assert!(net_rawstat.claim_inode(&inode, owner));
+        // the owner may re-claim, anyone else is refused
+        assert!(net_rawstat.claim_inode(&inode, owner));
+        assert!(!net_rawstat.claim_inode(&inode, intruder));
+    }
+
+    #[test]
+    #[cfg(feature = "network-capture")]
+    fn decreasing_counters_bump_the_reset_count() {
+        let mut prev_counters = HashMap::new();
+        let mut reset_counts = HashMap::new();
+        let conn = test_uni_conn();
+
+        let mut track = |bytes| {
+            track_counter_reset(
+                &mut prev_counters,
+                &mut reset_counts,
+                conn,
+                DataCount::from_byte(bytes),
+            )
+        };
+
+        // the first sighting and a normal increase leave the count at zero
+        assert_eq!(track(100), Count::new(0));
+        assert_eq!(track(250), Count::new(0));
+
+        // the counter going backwards means it was reset underneath us
+        assert_eq!(track(40), Count::new(1));
+
+        // and the count keeps accumulating across further resets
+        assert_eq!(track(90), Count::new(1));
+        assert_eq!(track(10), Count::new(2));
+    }
+
+    #[test]
+    fn connection_stats_serialize_in_a_deterministic_order() {
+        crate::setting::install_test_config();
+
+        let conn = |port| {
+            UniConnection::new(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                port,
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                80,
+                ConnectionType::TCP,
+            )
+        };
+        let build = |ports: &[u16]| {
+            let mut uni_connection_stats = HashMap::new();
+            for port in ports {
+                uni_connection_stats.insert(conn(*port), UniConnectionStat::new(conn(*port)));
+            }
+            InterfaceRawStat {
+                iname: String::from("eth0"),
+                description: String::new(),
+                uni_connection_stats,
+                connection_table_size: ports.len(),
+                link_type: None,
+                mtu: None,
+            }
+        };
+
+        // the same connections inserted in opposite orders serialize to
+        // byte-identical output, so sample-to-sample diffs stay quiet
+        let forward = serde_json::to_string(&build(&[1, 2, 3])).unwrap();
+        let backward = serde_json::to_string(&build(&[3, 2, 1])).unwrap();
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    #[cfg(feature = "network-capture")]
+    fn connection_table_evicts_the_oldest_beyond_the_cap() {
+        let conn = |port| {
+            UniConnection::new(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                port,
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                80,
+                ConnectionType::TCP,
+            )
+        };
+
+        let mut uni_conn_stats = HashMap::new();
+        let mut insertion_order = VecDeque::new();
+        for port in 1..=5 {
+            uni_conn_stats.insert(conn(port), UniConnectionStat::new(conn(port)));
+            insertion_order.push_back(conn(port));
+            evict_oldest_connections(&mut uni_conn_stats, &mut insertion_order, 3);
+        }
+
+        // the cap holds and the two oldest entries are the ones gone
+        assert_eq!(uni_conn_stats.len(), 3);
+        assert!(!uni_conn_stats.contains_key(&conn(1)));
+        assert!(!uni_conn_stats.contains_key(&conn(2)));
+        assert!(uni_conn_stats.contains_key(&conn(3)));
+        assert!(uni_conn_stats.contains_key(&conn(5)));
+    }
+}
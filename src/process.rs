@@ -1,18 +1,28 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::hash::{Hash, Hasher};
 use std::ops::{Add, AddAssign};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fmt, fs, io};
 
+use serde::ser::SerializeSeq;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::common::{CommonError, Count, DataCount, Gid, Inode, TimeCount, Timestamp, Uid};
 use crate::setting;
 use crate::network_stat::{Connection, NetworkRawStat, UniConnection, UniConnectionStat};
-use crate::taskstat::{TaskStatsConnection, TaskStatsError};
+use crate::taskstat::{TaskStatsError, TaskStatsSource};
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Pid(u128);
 
+impl Serialize for Pid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::common::serialize_large_number(self.0, serializer)
+    }
+}
+
 impl Pid {
     pub fn new(pid: usize) -> Self {
         Self(pid.try_into().unwrap())
@@ -48,13 +58,22 @@ impl<'de> Deserialize<'de> for Pid {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Tid(u128);
 
+impl Serialize for Tid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::common::serialize_large_number(self.0, serializer)
+    }
+}
+
 impl Tid {
     pub fn new(tid: usize) -> Self {
         Self(tid.try_into().unwrap())
     }
+    pub fn to_usize(&self) -> usize {
+        self.0 as usize
+    }
 }
 
 impl TryFrom<&str> for Tid {
@@ -92,6 +111,19 @@ pub struct ConnectionStat {
     // data count in higher level
     real_data_sent: DataCount,
     real_data_recv: DataCount,
+
+    // tcp retransmits where a data source provides them; the capture path
+    // can't see them, so this stays zero with retransmits_known = false
+    #[serde(skip_serializing_if = "setting::has_connection_stat_retransmits")]
+    retransmits: Count,
+
+    #[serde(skip_serializing_if = "setting::has_connection_stat_retransmits")]
+    retransmits_known: bool,
+
+    // unix seconds the connection was first seen by the capture table, and
+    // its lifetime as of this sample
+    first_seen_unix_secs: u64,
+    duration_secs: u64,
 }
 
 impl ConnectionStat {
@@ -107,6 +139,12 @@ impl ConnectionStat {
 
             real_data_sent: DataCount::from_byte(0),
             real_data_recv: DataCount::from_byte(0),
+
+            retransmits: Count::new(0),
+            retransmits_known: false,
+
+            first_seen_unix_secs: 0,
+            duration_secs: 0,
         }
     }
 
@@ -137,6 +175,17 @@ impl ConnectionStat {
     pub fn get_real_data_recv(&self) -> DataCount {
         self.real_data_recv
     }
+
+    #[allow(unused)]
+    pub fn get_retransmits(&self) -> Count {
+        self.retransmits
+    }
+
+    #[allow(unused)]
+    pub fn set_retransmits(&mut self, retransmits: Count) {
+        self.retransmits = retransmits;
+        self.retransmits_known = true;
+    }
 }
 
 impl Add<Self> for ConnectionStat {
@@ -159,6 +208,14 @@ impl Add<Self> for ConnectionStat {
 
             real_data_sent: self.real_data_sent + other.real_data_sent,
             real_data_recv: self.real_data_recv + other.real_data_recv,
+
+            // a merged count is only trustworthy when both sides knew theirs
+            retransmits: self.retransmits + other.retransmits,
+            retransmits_known: self.retransmits_known && other.retransmits_known,
+
+            // the merged lifetime starts at the earlier sighting
+            first_seen_unix_secs: self.first_seen_unix_secs.min(other.first_seen_unix_secs),
+            duration_secs: self.duration_secs.max(other.duration_secs),
         }
     }
 }
@@ -178,7 +235,113 @@ impl AddAssign<Self> for ConnectionStat {
 
         self.real_data_sent += other.real_data_sent;
         self.real_data_recv += other.real_data_recv;
+
+        self.retransmits += other.retransmits;
+        self.retransmits_known = self.retransmits_known && other.retransmits_known;
+
+        self.first_seen_unix_secs = self.first_seen_unix_secs.min(other.first_seen_unix_secs);
+        self.duration_secs = self.duration_secs.max(other.duration_secs);
+    }
+}
+
+// one prebuilt attribution record: everything the fd scan needs to hand a
+// connection stat to a process without re-walking the interface maps
+#[derive(Debug, Clone)]
+pub struct ConnectionIndexEntry {
+    iname: String,
+    conn_stat: ConnectionStat,
+    uni_conn: UniConnection,
+    reverse_uni_conn: UniConnection,
+}
+
+impl ConnectionIndexEntry {
+    pub fn interface_name(&self) -> &str {
+        &self.iname
+    }
+
+    pub fn uni_connections(&self) -> (UniConnection, UniConnection) {
+        (self.uni_conn, self.reverse_uni_conn)
+    }
+
+    pub fn into_parts(self) -> (String, ConnectionStat) {
+        (self.iname, self.conn_stat)
+    }
+}
+
+// build the inode → connection-stat index once per sample. the per-inode
+// path walked the interface maps for every socket of every process; on a
+// high-socket host this precomputation makes attribution a single lookup
+pub fn build_connection_stat_index(net_rawstat: &mut NetworkRawStat) {
+    let now_unix_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let mut index = HashMap::new();
+    for (inode, connection) in net_rawstat.connection_entries() {
+        let iname = match net_rawstat.lookup_interface_name(connection) {
+            Some(iname) => iname.to_string(),
+            None => continue,
+        };
+
+        let uni_conn = UniConnection::new(
+            connection.get_local_addr(),
+            connection.get_local_port(),
+            connection.get_remote_addr(),
+            connection.get_remote_port(),
+            connection.get_connection_type(),
+        );
+
+        let reverse_uni_conn = UniConnection::new(
+            connection.get_remote_addr(),
+            connection.get_remote_port(),
+            connection.get_local_addr(),
+            connection.get_local_port(),
+            connection.get_connection_type(),
+        );
+
+        // peek, don't mark: stats of connections never attributed this
+        // sample must survive for the next one
+        let uni_conn_stat = net_rawstat
+            .peek_uni_connection_stat(&iname, &uni_conn)
+            .cloned()
+            .unwrap_or(UniConnectionStat::new(uni_conn));
+
+        let reverse_uni_conn_stat = net_rawstat
+            .peek_uni_connection_stat(&iname, &reverse_uni_conn)
+            .cloned()
+            .unwrap_or(UniConnectionStat::new(reverse_uni_conn));
+
+        let mut conn_stat = ConnectionStat::new(connection.clone());
+
+        conn_stat.pack_sent = uni_conn_stat.get_packet_count();
+        conn_stat.pack_recv = reverse_uni_conn_stat.get_packet_count();
+
+        conn_stat.total_data_sent = uni_conn_stat.get_total_data_count();
+        conn_stat.total_data_recv = reverse_uni_conn_stat.get_total_data_count();
+
+        conn_stat.real_data_sent = uni_conn_stat.get_real_data_count();
+        conn_stat.real_data_recv = reverse_uni_conn_stat.get_real_data_count();
+
+        // first seen in either direction; duration is relative to this
+        // sample so it grows while the connection lives
+        conn_stat.first_seen_unix_secs = uni_conn_stat
+            .get_first_seen_unix_secs()
+            .min(reverse_uni_conn_stat.get_first_seen_unix_secs());
+        conn_stat.duration_secs = now_unix_secs.saturating_sub(conn_stat.first_seen_unix_secs);
+
+        index.insert(
+            *inode,
+            ConnectionIndexEntry {
+                iname,
+                conn_stat,
+                uni_conn,
+                reverse_uni_conn,
+            },
+        );
     }
+
+    net_rawstat.set_connection_stat_index(index);
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -315,11 +478,80 @@ impl AddAssign<Self> for InterfaceStat {
     }
 }
 
+// aggregate bucket for connection series beyond max_connection_series
+#[derive(Debug, Clone, Copy, Serialize)]
+struct OtherConnectionStat {
+    connection: &'static str,
+
+    pack_sent: Count,
+    pack_recv: Count,
+
+    total_data_sent: DataCount,
+    total_data_recv: DataCount,
+
+    real_data_sent: DataCount,
+    real_data_recv: DataCount,
+}
+
+impl OtherConnectionStat {
+    fn new() -> Self {
+        Self {
+            connection: "other",
+
+            pack_sent: Count::new(0),
+            pack_recv: Count::new(0),
+
+            total_data_sent: DataCount::from_byte(0),
+            total_data_recv: DataCount::from_byte(0),
+
+            real_data_sent: DataCount::from_byte(0),
+            real_data_recv: DataCount::from_byte(0),
+        }
+    }
+
+    fn absorb(&mut self, conn_stat: &ConnectionStat) {
+        self.pack_sent += conn_stat.get_pack_sent();
+        self.pack_recv += conn_stat.get_pack_recv();
+
+        self.total_data_sent += conn_stat.get_total_data_sent();
+        self.total_data_recv += conn_stat.get_total_data_recv();
+
+        self.real_data_sent += conn_stat.get_real_data_sent();
+        self.real_data_recv += conn_stat.get_real_data_recv();
+    }
+}
+
 fn get_interface_stat_conn_stats_serialize<S: Serializer>(
     input: &HashMap<Connection, ConnectionStat>,
     serializer: S,
 ) -> Result<S::Ok, S::Error> {
-    serializer.collect_seq(input.values())
+    let binding = setting::get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+
+    // sort by the connection tuple so both the output order and which
+    // connections fall into the "other" bucket are deterministic
+    let mut entries: Vec<(&Connection, &ConnectionStat)> = input.iter().collect();
+    entries.sort_by_key(|(connection, _)| **connection);
+
+    match glob_conf.get_max_connection_series() {
+        // too many series, collapse the excess into one "other" bucket
+        Some(cap) if entries.len() > cap => {
+            let mut seq = serializer.serialize_seq(Some(cap + 1))?;
+            let mut other = OtherConnectionStat::new();
+
+            for (index, (_, conn_stat)) in entries.into_iter().enumerate() {
+                if index < cap {
+                    seq.serialize_element(conn_stat)?;
+                } else {
+                    other.absorb(conn_stat);
+                }
+            }
+
+            seq.serialize_element(&other)?;
+            seq.end()
+        }
+        _ => serializer.collect_seq(entries.into_iter().map(|(_, conn_stat)| conn_stat)),
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -442,14 +674,36 @@ impl AddAssign<Self> for NetworkStat {
     }
 }
 
+// sorted by interface name for deterministic output
 fn get_netstat_interface_stats_serialize<S: Serializer>(
     input: &HashMap<String, InterfaceStat>,
     serializer: S,
 ) -> Result<S::Ok, S::Error> {
-    serializer.collect_seq(input.values())
+    let mut entries: Vec<(&String, &InterfaceStat)> = input.iter().collect();
+    entries.sort_by_key(|(iname, _)| *iname);
+    serializer.collect_seq(entries.into_iter().map(|(_, interface_stat)| interface_stat))
+}
+
+// which collection path produced the cpu/io numbers, they differ in fidelity
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatSource {
+    Taskstats,
+    Proc,
+    Mixed,
 }
 
-#[derive(Clone, Copy, Debug, Serialize)]
+impl StatSource {
+    pub fn combine(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            Self::Mixed
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct ThreadStat {
     #[serde(skip_serializing_if = "setting::has_thread_stat_timestamp")]
     timestamp: Timestamp,
@@ -474,6 +728,51 @@ pub struct ThreadStat {
 
     #[serde(skip_serializing_if = "setting::has_thread_stat_total_block_io_write")]
     total_block_io_write: DataCount,
+
+    #[serde(skip_serializing_if = "setting::has_thread_stat_minor_faults")]
+    minor_faults: Count,
+
+    #[serde(skip_serializing_if = "setting::has_thread_stat_major_faults")]
+    major_faults: Count,
+
+    // delay-accounting totals and counts, only used to derive the process's
+    // average waits
+    #[serde(skip_serializing)]
+    cpu_delay_total: TimeCount,
+
+    #[serde(skip_serializing)]
+    cpu_delay_count: Count,
+
+    #[serde(skip_serializing)]
+    block_io_delay_total: TimeCount,
+
+    #[serde(skip_serializing)]
+    block_io_delay_count: Count,
+
+    #[serde(skip_serializing)]
+    swapin_delay_total: TimeCount,
+
+    #[serde(skip_serializing)]
+    swapin_delay_count: Count,
+
+    // peak memory from taskstats, only used to max-merge into the process stat
+    #[serde(skip_serializing)]
+    peak_rss: DataCount,
+
+    #[serde(skip_serializing)]
+    peak_vss: DataCount,
+
+    // where the cpu/io numbers came from, only used to derive the process's source
+    #[serde(skip_serializing)]
+    stat_source: StatSource,
+
+    // cpu the thread last ran on, from /proc/<pid>/task/<tid>/stat
+    #[serde(skip_serializing_if = "setting::has_thread_stat_last_cpu")]
+    last_cpu: u32,
+
+    // affinity list from Cpus_allowed_list, e.g. "0-3,8"
+    #[serde(skip_serializing_if = "setting::has_thread_stat_cpu_affinity")]
+    cpu_affinity: String,
 }
 
 impl ThreadStat {
@@ -490,6 +789,24 @@ impl ThreadStat {
 
             total_block_io_read: DataCount::from_byte(0),
             total_block_io_write: DataCount::from_byte(0),
+
+            minor_faults: Count::new(0),
+            major_faults: Count::new(0),
+
+            cpu_delay_total: TimeCount::from_secs(0),
+            cpu_delay_count: Count::new(0),
+            block_io_delay_total: TimeCount::from_secs(0),
+            block_io_delay_count: Count::new(0),
+            swapin_delay_total: TimeCount::from_secs(0),
+            swapin_delay_count: Count::new(0),
+
+            peak_rss: DataCount::from_byte(0),
+            peak_vss: DataCount::from_byte(0),
+
+            stat_source: StatSource::Taskstats,
+
+            last_cpu: 0,
+            cpu_affinity: String::new(),
         }
     }
 
@@ -516,6 +833,42 @@ impl ThreadStat {
     pub fn get_total_block_io_write(&self) -> DataCount {
         self.total_block_io_write
     }
+
+    pub fn get_minor_faults(&self) -> Count {
+        self.minor_faults
+    }
+    pub fn get_major_faults(&self) -> Count {
+        self.major_faults
+    }
+
+    pub fn get_cpu_delay_total(&self) -> TimeCount {
+        self.cpu_delay_total
+    }
+    pub fn get_cpu_delay_count(&self) -> Count {
+        self.cpu_delay_count
+    }
+    pub fn get_block_io_delay_total(&self) -> TimeCount {
+        self.block_io_delay_total
+    }
+    pub fn get_block_io_delay_count(&self) -> Count {
+        self.block_io_delay_count
+    }
+    pub fn get_swapin_delay_total(&self) -> TimeCount {
+        self.swapin_delay_total
+    }
+    pub fn get_swapin_delay_count(&self) -> Count {
+        self.swapin_delay_count
+    }
+
+    pub fn get_peak_rss(&self) -> DataCount {
+        self.peak_rss
+    }
+    pub fn get_peak_vss(&self) -> DataCount {
+        self.peak_vss
+    }
+    pub fn get_stat_source(&self) -> StatSource {
+        self.stat_source
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -541,6 +894,13 @@ pub struct ProcessStat {
     #[serde(skip_serializing_if = "setting::has_process_stat_total_swap")]
     total_swap: DataCount,
 
+    // peaks are max-merged, not summed
+    #[serde(skip_serializing_if = "setting::has_process_stat_peak_rss")]
+    peak_rss: DataCount,
+
+    #[serde(skip_serializing_if = "setting::has_process_stat_peak_vss")]
+    peak_vss: DataCount,
+
     #[serde(skip_serializing_if = "setting::has_process_stat_total_io_read")]
     total_io_read: DataCount,
 
@@ -553,9 +913,76 @@ pub struct ProcessStat {
     #[serde(skip_serializing_if = "setting::has_process_stat_total_block_io_write")]
     total_block_io_write: DataCount,
 
+    #[serde(skip_serializing_if = "setting::has_process_stat_minor_faults")]
+    minor_faults: Count,
+
+    #[serde(skip_serializing_if = "setting::has_process_stat_major_faults")]
+    major_faults: Count,
+
+    // average delay-accounting waits per event, derived from the summed
+    // totals and counts of all threads (and merged descendants)
+    #[serde(skip_serializing_if = "setting::has_process_stat_delay_avgs")]
+    cpu_delay_avg: TimeCount,
+
+    #[serde(skip_serializing_if = "setting::has_process_stat_delay_avgs")]
+    block_io_delay_avg: TimeCount,
+
+    #[serde(skip_serializing_if = "setting::has_process_stat_delay_avgs")]
+    swapin_delay_avg: TimeCount,
+
+    // the raw delay totals/counts behind the averages
+    #[serde(skip_serializing)]
+    cpu_delay_total: TimeCount,
+
+    #[serde(skip_serializing)]
+    cpu_delay_count: Count,
+
+    #[serde(skip_serializing)]
+    block_io_delay_total: TimeCount,
+
+    #[serde(skip_serializing)]
+    block_io_delay_count: Count,
+
+    #[serde(skip_serializing)]
+    swapin_delay_total: TimeCount,
+
+    #[serde(skip_serializing)]
+    swapin_delay_count: Count,
+
+    // which path produced the cpu/io numbers
+    stat_source: StatSource,
+
+    // per-interval deltas of the cumulative counters, only filled when
+    // emit_deltas is on and computed after thread/descendant aggregation
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    deltas: Option<StatDeltas>,
+
     netstat: NetworkStat,
 }
 
+// deltas against the previous sample for the cumulative process counters
+#[derive(Debug, Clone, Serialize)]
+pub struct StatDeltas {
+    total_system_cpu_time_delta: TimeCount,
+    total_user_cpu_time_delta: TimeCount,
+    total_cpu_time_delta: TimeCount,
+
+    total_io_read_delta: DataCount,
+    total_io_write_delta: DataCount,
+
+    total_block_io_read_delta: DataCount,
+    total_block_io_write_delta: DataCount,
+
+    total_data_sent_delta: DataCount,
+    total_data_recv_delta: DataCount,
+
+    // cpu usage over the interval as a percentage of one cpu, so a fully
+    // busy 4-thread process reads 400. absent on the first sample, where
+    // there is no baseline to measure against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_percent: Option<f64>,
+}
+
 impl ProcessStat {
     pub fn new() -> Self {
         Self {
@@ -569,23 +996,159 @@ impl ProcessStat {
             total_vss: DataCount::from_byte(0),
             total_swap: DataCount::from_byte(0),
 
+            peak_rss: DataCount::from_byte(0),
+            peak_vss: DataCount::from_byte(0),
+
             total_io_read: DataCount::from_byte(0),
             total_io_write: DataCount::from_byte(0),
 
             total_block_io_read: DataCount::from_byte(0),
             total_block_io_write: DataCount::from_byte(0),
 
+            minor_faults: Count::new(0),
+            major_faults: Count::new(0),
+
+            cpu_delay_avg: TimeCount::from_secs(0),
+            block_io_delay_avg: TimeCount::from_secs(0),
+            swapin_delay_avg: TimeCount::from_secs(0),
+
+            cpu_delay_total: TimeCount::from_secs(0),
+            cpu_delay_count: Count::new(0),
+            block_io_delay_total: TimeCount::from_secs(0),
+            block_io_delay_count: Count::new(0),
+            swapin_delay_total: TimeCount::from_secs(0),
+            swapin_delay_count: Count::new(0),
+
+            stat_source: StatSource::Taskstats,
+
+            deltas: None,
+
             netstat: NetworkStat::new(),
         }
     }
+
+    // refresh the *_avg fields from the summed totals and counts; a zero
+    // count just yields a zero average
+    fn compute_delay_avgs(&mut self) {
+        self.cpu_delay_avg = self.cpu_delay_total.avg_per(self.cpu_delay_count);
+        self.block_io_delay_avg = self.block_io_delay_total.avg_per(self.block_io_delay_count);
+        self.swapin_delay_avg = self.swapin_delay_total.avg_per(self.swapin_delay_count);
+    }
+
+    pub fn get_stat_source(&self) -> StatSource {
+        self.stat_source
+    }
+    pub fn set_stat_source(&mut self, stat_source: StatSource) {
+        self.stat_source = stat_source;
+    }
+
+    pub fn get_total_cpu_time(&self) -> TimeCount {
+        self.total_cpu_time
+    }
+    pub fn get_total_rss(&self) -> DataCount {
+        self.total_rss
+    }
+    pub fn get_total_io_read(&self) -> DataCount {
+        self.total_io_read
+    }
+    pub fn get_total_io_write(&self) -> DataCount {
+        self.total_io_write
+    }
+
+    // fill the *_delta fields from the previous sample of the same process.
+    // a process seen for the first time reports the full value, and a counter
+    // reset (new value below the old one) clamps the delta to zero
+    pub fn compute_deltas(&mut self, previous: Option<&ProcessStat>) {
+        self.deltas = Some(match previous {
+            Some(prev) => StatDeltas {
+                cpu_percent: cpu_percent_over_interval(
+                    self.total_cpu_time
+                        .checked_sub(prev.total_cpu_time)
+                        .unwrap_or(TimeCount::from_secs(0)),
+                    self.timestamp.nanosecs_since(&prev.timestamp),
+                ),
+                total_system_cpu_time_delta: self
+                    .total_system_cpu_time
+                    .checked_sub(prev.total_system_cpu_time)
+                    .unwrap_or(TimeCount::from_secs(0)),
+                total_user_cpu_time_delta: self
+                    .total_user_cpu_time
+                    .checked_sub(prev.total_user_cpu_time)
+                    .unwrap_or(TimeCount::from_secs(0)),
+                total_cpu_time_delta: self
+                    .total_cpu_time
+                    .checked_sub(prev.total_cpu_time)
+                    .unwrap_or(TimeCount::from_secs(0)),
+
+                total_io_read_delta: self
+                    .total_io_read
+                    .checked_sub(prev.total_io_read)
+                    .unwrap_or(DataCount::from_byte(0)),
+                total_io_write_delta: self
+                    .total_io_write
+                    .checked_sub(prev.total_io_write)
+                    .unwrap_or(DataCount::from_byte(0)),
+
+                total_block_io_read_delta: self
+                    .total_block_io_read
+                    .checked_sub(prev.total_block_io_read)
+                    .unwrap_or(DataCount::from_byte(0)),
+                total_block_io_write_delta: self
+                    .total_block_io_write
+                    .checked_sub(prev.total_block_io_write)
+                    .unwrap_or(DataCount::from_byte(0)),
+
+                total_data_sent_delta: self
+                    .netstat
+                    .total_data_sent
+                    .checked_sub(prev.netstat.total_data_sent)
+                    .unwrap_or(DataCount::from_byte(0)),
+                total_data_recv_delta: self
+                    .netstat
+                    .total_data_recv
+                    .checked_sub(prev.netstat.total_data_recv)
+                    .unwrap_or(DataCount::from_byte(0)),
+            },
+            None => StatDeltas {
+                cpu_percent: None,
+                total_system_cpu_time_delta: self.total_system_cpu_time,
+                total_user_cpu_time_delta: self.total_user_cpu_time,
+                total_cpu_time_delta: self.total_cpu_time,
+
+                total_io_read_delta: self.total_io_read,
+                total_io_write_delta: self.total_io_write,
+
+                total_block_io_read_delta: self.total_block_io_read,
+                total_block_io_write_delta: self.total_block_io_write,
+
+                total_data_sent_delta: self.netstat.total_data_sent,
+                total_data_recv_delta: self.netstat.total_data_recv,
+            },
+        });
+    }
+}
+
+// cpu time spent over wall time, scaled to percent of one cpu and clamped
+// to [0, 100 * online cpus]; a zero or backwards interval yields no value
+fn cpu_percent_over_interval(cpu_time_delta: TimeCount, interval_nanosecs: u128) -> Option<f64> {
+    if interval_nanosecs == 0 {
+        return None;
+    }
+    let num_cpus = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let percent = cpu_time_delta.as_nanosecs() as f64 / interval_nanosecs as f64 * 100.0;
+    Some(percent.clamp(0.0, 100.0 * num_cpus as f64))
 }
 
 impl Add<Self> for ProcessStat {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
-        Self {
-            timestamp: self.timestamp,
+        let mut result = Self {
+            // accumulation across time carries the latest timestamp, so a
+            // merged stat is dated by its freshest operand
+            timestamp: self.timestamp.max(other.timestamp),
 
             total_system_cpu_time: self.total_system_cpu_time + other.total_system_cpu_time,
             total_user_cpu_time: self.total_user_cpu_time + other.total_user_cpu_time,
@@ -595,14 +1158,39 @@ impl Add<Self> for ProcessStat {
             total_vss: self.total_vss + other.total_vss,
             total_swap: self.total_swap + other.total_swap,
 
+            peak_rss: self.peak_rss.max(other.peak_rss),
+            peak_vss: self.peak_vss.max(other.peak_vss),
+
             total_io_read: self.total_io_read + other.total_io_read,
             total_io_write: self.total_io_write + other.total_io_write,
 
             total_block_io_read: self.total_block_io_read + other.total_block_io_read,
             total_block_io_write: self.total_block_io_write + other.total_block_io_write,
 
+            minor_faults: self.minor_faults + other.minor_faults,
+            major_faults: self.major_faults + other.major_faults,
+
+            cpu_delay_avg: TimeCount::from_secs(0),
+            block_io_delay_avg: TimeCount::from_secs(0),
+            swapin_delay_avg: TimeCount::from_secs(0),
+
+            cpu_delay_total: self.cpu_delay_total + other.cpu_delay_total,
+            cpu_delay_count: self.cpu_delay_count + other.cpu_delay_count,
+            block_io_delay_total: self.block_io_delay_total + other.block_io_delay_total,
+            block_io_delay_count: self.block_io_delay_count + other.block_io_delay_count,
+            swapin_delay_total: self.swapin_delay_total + other.swapin_delay_total,
+            swapin_delay_count: self.swapin_delay_count + other.swapin_delay_count,
+
+            stat_source: self.stat_source.combine(other.stat_source),
+
+            // merged stats need their deltas recomputed
+            deltas: None,
+
             netstat: self.netstat + other.netstat,
-        }
+        };
+
+        result.compute_delay_avgs();
+        result
     }
 }
 
@@ -610,30 +1198,61 @@ impl Add<ThreadStat> for ProcessStat {
     type Output = Self;
 
     fn add(self, other: ThreadStat) -> Self {
-        Self {
-            timestamp: self.timestamp,
+        let mut result = Self {
+            // latest timestamp among operands, same rule as process+process
+            timestamp: self.timestamp.max(other.timestamp),
 
             total_system_cpu_time: self.total_system_cpu_time + other.get_total_system_cpu_time(),
             total_user_cpu_time: self.total_user_cpu_time + other.get_total_user_cpu_time(),
             total_cpu_time: self.total_cpu_time + other.get_total_cpu_time(),
 
+            // threads share their process's address space, so summing their
+            // rss/vss/swap would count the same pages once per thread; the
+            // process-level numbers already cover all of them
             total_rss: self.total_rss,
             total_vss: self.total_vss,
             total_swap: self.total_swap,
 
+            peak_rss: self.peak_rss.max(other.get_peak_rss()),
+            peak_vss: self.peak_vss.max(other.get_peak_vss()),
+
             total_io_read: self.total_io_read + other.get_total_io_read(),
             total_io_write: self.total_io_write + other.get_total_io_write(),
 
             total_block_io_read: self.total_block_io_read + other.get_total_block_io_read(),
             total_block_io_write: self.total_block_io_write + other.get_total_block_io_write(),
 
+            minor_faults: self.minor_faults + other.get_minor_faults(),
+            major_faults: self.major_faults + other.get_major_faults(),
+
+            cpu_delay_avg: TimeCount::from_secs(0),
+            block_io_delay_avg: TimeCount::from_secs(0),
+            swapin_delay_avg: TimeCount::from_secs(0),
+
+            cpu_delay_total: self.cpu_delay_total + other.get_cpu_delay_total(),
+            cpu_delay_count: self.cpu_delay_count + other.get_cpu_delay_count(),
+            block_io_delay_total: self.block_io_delay_total + other.get_block_io_delay_total(),
+            block_io_delay_count: self.block_io_delay_count + other.get_block_io_delay_count(),
+            swapin_delay_total: self.swapin_delay_total + other.get_swapin_delay_total(),
+            swapin_delay_count: self.swapin_delay_count + other.get_swapin_delay_count(),
+
+            stat_source: self.stat_source,
+
+            deltas: self.deltas,
+
             netstat: self.netstat,
-        }
+        };
+
+        result.compute_delay_avgs();
+        result
     }
 }
 
 impl AddAssign<Self> for ProcessStat {
     fn add_assign(&mut self, other: Self) {
+        // latest timestamp among operands, matching the Add impls
+        self.timestamp = self.timestamp.max(other.timestamp);
+
         self.total_system_cpu_time += other.total_system_cpu_time;
         self.total_user_cpu_time += other.total_user_cpu_time;
         self.total_cpu_time += other.total_cpu_time;
@@ -642,27 +1261,64 @@ impl AddAssign<Self> for ProcessStat {
         self.total_vss += other.total_vss;
         self.total_swap += other.total_swap;
 
+        self.peak_rss = self.peak_rss.max(other.peak_rss);
+        self.peak_vss = self.peak_vss.max(other.peak_vss);
+
         self.total_io_read += other.total_io_read;
         self.total_io_write += other.total_io_write;
 
         self.total_block_io_read += other.total_block_io_read;
         self.total_block_io_write += other.total_block_io_write;
 
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+
+        self.cpu_delay_total += other.cpu_delay_total;
+        self.cpu_delay_count += other.cpu_delay_count;
+        self.block_io_delay_total += other.block_io_delay_total;
+        self.block_io_delay_count += other.block_io_delay_count;
+        self.swapin_delay_total += other.swapin_delay_total;
+        self.swapin_delay_count += other.swapin_delay_count;
+        self.compute_delay_avgs();
+
+        self.stat_source = self.stat_source.combine(other.stat_source);
+
+        // merged stats need their deltas recomputed
+        self.deltas = None;
+
         self.netstat += other.netstat;
     }
 }
 
+// rss/vss/swap are deliberately untouched here: threads share their
+// process's address space and the process-level numbers already cover them
 impl AddAssign<ThreadStat> for ProcessStat {
     fn add_assign(&mut self, other: ThreadStat) {
+        self.timestamp = self.timestamp.max(other.timestamp);
+
         self.total_system_cpu_time += other.get_total_system_cpu_time();
         self.total_user_cpu_time += other.get_total_user_cpu_time();
         self.total_cpu_time += other.get_total_cpu_time();
 
+        self.peak_rss = self.peak_rss.max(other.get_peak_rss());
+        self.peak_vss = self.peak_vss.max(other.get_peak_vss());
+
         self.total_io_read += other.get_total_io_read();
         self.total_io_write += other.get_total_io_write();
 
         self.total_block_io_read += other.get_total_block_io_read();
         self.total_block_io_write += other.get_total_block_io_write();
+
+        self.minor_faults += other.get_minor_faults();
+        self.major_faults += other.get_major_faults();
+
+        self.cpu_delay_total += other.get_cpu_delay_total();
+        self.cpu_delay_count += other.get_cpu_delay_count();
+        self.block_io_delay_total += other.get_block_io_delay_total();
+        self.block_io_delay_count += other.get_block_io_delay_count();
+        self.swapin_delay_total += other.get_swapin_delay_total();
+        self.swapin_delay_count += other.get_swapin_delay_count();
+        self.compute_delay_avgs();
     }
 }
 
@@ -682,6 +1338,11 @@ pub struct Thread {
     #[serde(skip_serializing_if = "setting::has_thread_real_pid")]
     real_pid: Pid,
 
+    // the group leader (tid == pid) only ever carries its own numbers here,
+    // see get_stat; the flag just lets consumers tell it apart
+    #[serde(skip_serializing_if = "setting::has_thread_is_group_leader")]
+    is_group_leader: bool,
+
     // this thread stat
     stat: ThreadStat,
 }
@@ -695,16 +1356,26 @@ impl Thread {
             real_tid,
             real_pid,
 
+            is_group_leader: real_tid.to_usize() == real_pid.to_usize(),
+
             stat: ThreadStat::new(),
         }
     }
 
-    // update this thread stat, and return a copy of it
+    // update this thread stat, and return a copy of it.
+    // this always queries the per-thread (PID attr) taskstats, never the TGID
+    // aggregate, so the group leader's numbers cover the leader thread only and
+    // summing all threads into the process counts each thread exactly once
     pub fn get_stat(
         &mut self,
-        taskstats_conn: &TaskStatsConnection,
+        taskstats_conn: &dyn TaskStatsSource,
     ) -> Result<ThreadStat, ProcessError> {
-        let thread_taskstats = taskstats_conn.get_thread_taskstats(self.real_tid)?;
+        let thread_taskstats = match taskstats_conn.thread_taskstats(self.real_tid) {
+            Ok(thread_taskstats) => thread_taskstats,
+            // final fallback when taskstats is down: cpu times still come
+            // from /proc, the io counters just stay zero
+            Err(_) => return self.get_stat_from_proc(),
+        };
 
         self.stat.total_system_cpu_time = thread_taskstats.system_cpu_time;
         self.stat.total_user_cpu_time = thread_taskstats.user_cpu_time;
@@ -717,8 +1388,72 @@ impl Thread {
         self.stat.total_block_io_read = thread_taskstats.block_io_read;
         self.stat.total_block_io_write = thread_taskstats.block_io_write;
 
-        Ok(self.stat)
+        self.stat.minor_faults = thread_taskstats.minor_fault_count;
+        self.stat.major_faults = thread_taskstats.major_fault_count;
+
+        self.stat.cpu_delay_total = thread_taskstats.cpu_delay_total;
+        self.stat.cpu_delay_count = thread_taskstats.cpu_delay_count;
+        self.stat.block_io_delay_total = thread_taskstats.block_io_delay_total;
+        self.stat.block_io_delay_count = thread_taskstats.block_io_delay_count;
+        self.stat.swapin_delay_total = thread_taskstats.swapin_delay_total;
+        self.stat.swapin_delay_count = thread_taskstats.swapin_delay_count;
+
+        self.stat.peak_rss = thread_taskstats.high_water_rss;
+        self.stat.peak_vss = thread_taskstats.high_water_vss;
+
+        self.stat.stat_source = StatSource::Taskstats;
+
+        Ok(self.stat.clone())
     }
+
+    // cpu times from /proc/<pid>/task/<tid>/stat fields 14/15, in clock ticks
+    fn get_stat_from_proc(&mut self) -> Result<ThreadStat, ProcessError> {
+        let stat_content = fs::read_to_string(format!(
+            "/proc/{}/task/{}/stat",
+            self.real_pid, self.real_tid
+        ))?;
+        let stat_fields: Vec<&str> = stat_content
+            .rsplit(')')
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .collect();
+
+        // utime and stime are fields 14/15, index 11/12 after the command
+        let utime_ticks: usize = stat_fields
+            .get(11)
+            .and_then(|ticks| ticks.parse().ok())
+            .unwrap_or(0);
+        let stime_ticks: usize = stat_fields
+            .get(12)
+            .and_then(|ticks| ticks.parse().ok())
+            .unwrap_or(0);
+
+        let ticks_per_second = clock_ticks_per_second();
+        self.stat.total_user_cpu_time = ticks_to_time(utime_ticks, ticks_per_second);
+        self.stat.total_system_cpu_time = ticks_to_time(stime_ticks, ticks_per_second);
+        self.stat.total_cpu_time = self.stat.total_user_cpu_time + self.stat.total_system_cpu_time;
+
+        self.stat.stat_source = StatSource::Proc;
+
+        Ok(self.stat.clone())
+    }
+}
+
+// clock ticks per second from sysconf(_SC_CLK_TCK), 100 on effectively
+// every linux but queried anyway
+fn clock_ticks_per_second() -> usize {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 {
+        ticks as usize
+    } else {
+        100
+    }
+}
+
+// convert a clock-tick counter to elapsed time
+fn ticks_to_time(ticks: usize, ticks_per_second: usize) -> TimeCount {
+    TimeCount::from_nanosecs(ticks * (1_000_000_000 / ticks_per_second))
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -753,6 +1488,32 @@ pub struct Process {
     #[serde(skip_serializing_if = "setting::has_process_fs_gid")]
     fs_gid: Gid,
 
+    // supplementary groups from the Groups: line, mapped into the namespace
+    #[serde(skip_serializing_if = "setting::has_process_supplementary_gids")]
+    supplementary_gids: Vec<Gid>,
+
+    // sorted unique local ports of this process's LISTEN sockets
+    #[serde(skip_serializing_if = "setting::has_process_listening_ports")]
+    listening_ports: Vec<u16>,
+
+    // number of socket fds in /proc/<pid>/fd, a cheap connection-leak signal
+    #[serde(skip_serializing_if = "setting::has_process_socket_count")]
+    socket_count: usize,
+
+    // socket fds of protocols the sensor doesn't attribute (sctp, udplite,
+    // raw), skipped cleanly instead of being misread as tcp/udp
+    #[serde(skip_serializing_if = "setting::has_process_socket_count")]
+    unsupported_socket_count: usize,
+
+    // sockets that matched a connection entry, feeding the coverage object
+    // rather than being serialized per process
+    #[serde(skip_serializing)]
+    attributed_socket_count: usize,
+
+    // only set when include_kernel_threads keeps a kernel thread around
+    #[serde(skip_serializing_if = "is_false")]
+    kernel_thread: bool,
+
     // ids outside namespace
     #[serde(skip_serializing_if = "setting::has_process_real_pid")]
     real_pid: Pid, // Must have
@@ -790,6 +1551,31 @@ pub struct Process {
     #[serde(skip_serializing_if = "setting::has_process_command")]
     command: String,
 
+    // logical service name from the command_normalization rules, absent
+    // unless rules are configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normalized_command: Option<String>,
+
+    // set when max_string_len cut any of the string fields above
+    #[serde(skip_serializing_if = "is_false")]
+    strings_truncated: bool,
+
+    // allow-listed env vars from /proc/<pid>/environ, empty unless configured
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    env: HashMap<String, String>,
+
+    // stable identity hash of (start_time, real_pid, exec_path), survives pid reuse
+    #[serde(skip_serializing_if = "setting::has_process_process_uid")]
+    process_uid: String,
+
+    // session id from /proc/<pid>/stat, groups login sessions vs daemons
+    #[serde(skip_serializing_if = "setting::has_process_session_id")]
+    session_id: Pid,
+
+    // controlling terminal, 0 means no tty
+    #[serde(skip_serializing_if = "setting::has_process_tty_nr")]
+    tty_nr: i32,
+
     // accumulated thread stat of all threads of this process
     stat: ProcessStat,
 
@@ -798,6 +1584,14 @@ pub struct Process {
 
     #[serde(skip_serializing_if = "setting::has_process_child_real_pid_list")]
     child_real_pid_list: Vec<Pid>,
+
+    // fan-out metrics: direct children, and the whole subtree emitted by
+    // the tree walk (so pids a walk skipped are not counted)
+    #[serde(skip_serializing_if = "setting::has_process_child_count")]
+    child_count: usize,
+
+    #[serde(skip_serializing_if = "setting::has_process_child_count")]
+    descendant_count: usize,
 }
 
 impl Process {
@@ -839,6 +1633,13 @@ impl Process {
             saved_gid,
             fs_gid,
 
+            supplementary_gids: Vec::new(),
+            listening_ports: Vec::new(),
+            socket_count: 0,
+            unsupported_socket_count: 0,
+            attributed_socket_count: 0,
+            kernel_thread: false,
+
             real_pid,
             real_parent_pid,
 
@@ -854,12 +1655,63 @@ impl Process {
 
             exec_path,
             command,
+            normalized_command: None,
+            strings_truncated: false,
+
+            env: HashMap::new(),
+
+            process_uid: String::new(),
+
+            session_id: Pid::new(0),
+            tty_nr: 0,
 
             stat: ProcessStat::new(),
             threads: Vec::new(),
             child_real_pid_list: Vec::new(),
+            child_count: 0,
+            descendant_count: 0,
         }
     }
+
+    pub fn get_stat(&self) -> &ProcessStat {
+        &self.stat
+    }
+
+    pub fn get_process_uid(&self) -> String {
+        self.process_uid.clone()
+    }
+
+    // tests build processes directly and stamp the identity and stats that
+    // normally come from /proc collection
+    #[cfg(test)]
+    pub fn set_process_uid(&mut self, process_uid: String) {
+        self.process_uid = process_uid;
+    }
+
+    #[cfg(test)]
+    pub fn set_stat(&mut self, stat: ProcessStat) {
+        self.stat = stat;
+    }
+
+    pub fn get_real_pid(&self) -> Pid {
+        self.real_pid
+    }
+
+    pub fn get_command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn get_socket_count(&self) -> usize {
+        self.socket_count
+    }
+
+    pub fn get_attributed_socket_count(&self) -> usize {
+        self.attributed_socket_count
+    }
+
+    pub fn compute_stat_deltas(&mut self, previous: Option<&ProcessStat>) {
+        self.stat.compute_deltas(previous);
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -934,6 +1786,12 @@ impl UidMap {
     }
 
     pub fn map_to_uid(&self, real_uid: Uid) -> Option<Uid> {
+        // an empty uid_map is the initial namespace, where every uid maps
+        // to itself
+        if self.uid_map_entries.is_empty() {
+            return Some(real_uid);
+        }
+
         for uid_map_entry in &self.uid_map_entries {
             if let Some(uid) = uid_map_entry.map_to_uid(real_uid) {
                 return Some(uid);
@@ -1049,6 +1907,12 @@ impl GidMap {
     }
 
     pub fn map_to_gid(&self, real_gid: Gid) -> Option<Gid> {
+        // an empty gid_map is the initial namespace, where every gid maps
+        // to itself
+        if self.gid_map_entries.is_empty() {
+            return Some(real_gid);
+        }
+
         for gid_map_entry in &self.gid_map_entries {
             if let Some(gid) = gid_map_entry.map_to_gid(real_gid) {
                 return Some(gid);
@@ -1092,10 +1956,76 @@ impl TryFrom<&str> for GidMap {
     }
 }
 
+// serde gate for the strings_truncated flag
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// clamp a string to at most max_len characters, marking the cut with "...";
+// returns whether anything was cut
+fn truncate_with_marker(value: &mut String, max_len: usize) -> bool {
+    if value.chars().count() <= max_len {
+        return false;
+    }
+
+    let mut truncated: String = value.chars().take(max_len).collect();
+    truncated.push_str("...");
+    *value = truncated;
+    true
+}
+
+// look up a "Key:" line by name in a status file and return its trimmed value.
+// fields like NSpid or VmRSS are absent on some kernels and for kernel
+// threads, so callers must handle None instead of indexing by line number
+pub fn status_value<'a>(status_lines: &[&'a str], key: &str) -> Option<&'a str> {
+    status_lines
+        .iter()
+        .find(|line| line.starts_with(key))
+        .map(|line| line[key.len()..].trim())
+}
+
+// all the init-process parent rules in one place:
+// - pid 1 has no parent, both parents are 0 and /proc/0/status is never read
+// - on old kernels namespace pids are unavailable, parent_pid is 0
+// - a namespace-init (pid 1 inside its namespace) also reports parent_pid 0
+// - a parent that exited between reads surfaces as an io error to the caller
+fn resolve_parent_pids(
+    real_pid: &Pid,
+    pid: Pid,
+    status_lines: &[&str],
+    old_kernel: bool,
+) -> Result<(Pid, Pid), ProcessError> {
+    if *real_pid == Pid::new(1) {
+        return Ok((Pid::new(0), Pid::new(0)));
+    }
+
+    let real_parent_pid = Pid::try_from(status_value(status_lines, "PPid:").unwrap_or("0"))?;
+
+    if old_kernel || pid == Pid::new(1) {
+        return Ok((Pid::new(0), real_parent_pid));
+    }
+
+    let parent_status_file_content =
+        fs::read_to_string(format!("/proc/{}/status", real_parent_pid))?;
+
+    let parent_lines: Vec<&str> = parent_status_file_content.lines().collect();
+
+    // no NSpid line means pid namespaces aren't compiled in, the namespaced
+    // pid is the real one
+    let parent_pid = match status_value(&parent_lines, "NSpid:")
+        .and_then(|values| values.split_whitespace().last())
+    {
+        Some(parent_pid) => Pid::try_from(parent_pid)?,
+        None => real_parent_pid,
+    };
+
+    Ok((parent_pid, real_parent_pid))
+}
+
 // Make a process from realPid, with all data pulled from running system
 pub fn get_real_proc(
     real_pid: &Pid,
-    taskstats_conn: &TaskStatsConnection,
+    taskstats_conn: &dyn TaskStatsSource,
     net_rawstat: &mut NetworkRawStat,
 ) -> Result<Process, ProcessError> {
     let status_file_content = fs::read_to_string(format!("/proc/{}/status", real_pid))?;
@@ -1105,55 +2035,41 @@ pub fn get_real_proc(
     let binding = setting::get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
 
-    // get pid
+    // get pid, a missing NSpid line means no pid namespaces so the
+    // namespaced pid equals the real one
     let pid = if glob_conf.is_old_kernel() {
         Pid::new(0)
     } else {
-        let pids = lines[12].split_whitespace().collect::<Vec<&str>>();
-        println!("{:?}", lines);
-        Pid::try_from(pids[pids.len() - 1]).unwrap()
-    };
-
-    // get realParentPid
-    let real_parent_pid = if *real_pid == Pid::new(1) {
-        Pid::new(0)
-    } else {
-        Pid::try_from(lines[6].split_whitespace().collect::<Vec<&str>>()[1])?
-    };
-
-    // get parentPid
-    let parent_pid = if glob_conf.is_old_kernel() {
-        Pid::new(0)
-    } else if *real_pid == Pid::new(1) {
-        Pid::new(0)
-    } else {
-        let parent_status_file_content =
-            fs::read_to_string(format!("/proc/{}/status", real_parent_pid))?;
-
-        let parent_lines: Vec<&str> = parent_status_file_content.lines().collect();
-        let parent_pids = parent_lines[12].split_whitespace().collect::<Vec<&str>>();
-
-        if pid != Pid::new(1) {
-            Pid::try_from(parent_pids[parent_pids.len() - 1])?
-        } else {
-            Pid::new(0)
+        match status_value(&lines, "NSpid:").and_then(|values| values.split_whitespace().last()) {
+            Some(pid) => Pid::try_from(pid)?,
+            None => *real_pid,
         }
     };
 
-    // get real uids and gids
-    let real_gids = lines[9].split_whitespace().collect::<Vec<&str>>();
-    let real_uids = lines[8].split_whitespace().collect::<Vec<&str>>();
+    // get parentPid and realParentPid, the pid-1 rules live in resolve_parent_pids
+    let (parent_pid, real_parent_pid) =
+        resolve_parent_pids(real_pid, pid, &lines, glob_conf.is_old_kernel())?;
 
-    let real_uid = Uid::try_from(real_uids[1]).unwrap();
+    // get real uids and gids, tolerating short or malformed lines
+    let real_uids = status_value(&lines, "Uid:")
+        .unwrap_or("0")
+        .split_whitespace()
+        .collect::<Vec<&str>>();
+    let real_gids = status_value(&lines, "Gid:")
+        .unwrap_or("0")
+        .split_whitespace()
+        .collect::<Vec<&str>>();
 
-    let real_effective_uid = Uid::try_from(real_uids[2]).unwrap();
-    let real_saved_uid = Uid::try_from(real_uids[3]).unwrap();
-    let real_fs_uid = Uid::try_from(real_uids[4]).unwrap();
+    let real_uid = Uid::try_from(*real_uids.first().unwrap_or(&"0"))?;
 
-    let real_gid = Gid::try_from(real_gids[1]).unwrap();
-    let real_effective_gid = Gid::try_from(real_gids[2]).unwrap();
-    let real_saved_gid = Gid::try_from(real_gids[3]).unwrap();
-    let real_fs_gid = Gid::try_from(real_gids[4]).unwrap();
+    let real_effective_uid = Uid::try_from(*real_uids.get(1).unwrap_or(&"0"))?;
+    let real_saved_uid = Uid::try_from(*real_uids.get(2).unwrap_or(&"0"))?;
+    let real_fs_uid = Uid::try_from(*real_uids.get(3).unwrap_or(&"0"))?;
+
+    let real_gid = Gid::try_from(*real_gids.first().unwrap_or(&"0"))?;
+    let real_effective_gid = Gid::try_from(*real_gids.get(1).unwrap_or(&"0"))?;
+    let real_saved_gid = Gid::try_from(*real_gids.get(2).unwrap_or(&"0"))?;
+    let real_fs_gid = Gid::try_from(*real_gids.get(3).unwrap_or(&"0"))?;
 
     // map real uids and real gids to uids and gids
     let uid_map =
@@ -1173,9 +2089,24 @@ pub fn get_real_proc(
     let saved_gid = gid_map.map_to_gid(real_saved_gid).unwrap();
     let fs_gid = gid_map.map_to_gid(real_fs_gid).unwrap();
 
-    // get execution path
-    let exec_path = fs::read_link(format!("/proc/{}/exe", real_pid))?;
-    let exec_path = exec_path.as_path().to_str().unwrap().to_string();
+    // get execution path. kernel threads (no exe link, parented to kthreadd)
+    // are usually noise: skipped by default, kept with a marker when opted in
+    let mut kernel_thread = false;
+    let exec_path = match fs::read_link(format!("/proc/{}/exe", real_pid)) {
+        Ok(exec_path) => exec_path.as_path().to_str().unwrap().to_string(),
+        Err(err) => {
+            let kthreadd_pid = Pid::new(2);
+            if real_parent_pid != kthreadd_pid && *real_pid != kthreadd_pid {
+                return Err(ProcessError::IOErr(err));
+            }
+            if !glob_conf.get_include_kernel_threads() {
+                return Err(ProcessError::KernelThreadSkipped(*real_pid));
+            }
+
+            kernel_thread = true;
+            String::new()
+        }
+    };
 
     // get command
     let command = fs::read_to_string(format!("/proc/{}/comm", real_pid))?;
@@ -1204,23 +2135,107 @@ pub fn get_real_proc(
         exec_path,
         command,
     );
+    proc.kernel_thread = kernel_thread;
+
+    // compute the stable identity hash, pid reuse changes start_time so a
+    // reused pid shows up as a new instance
+    let stat_data = fs::read_to_string(format!("/proc/{}/stat", proc.real_pid))?;
+    let stat_fields: Vec<&str> = stat_data
+        .rsplit(')')
+        .next()
+        .unwrap_or("")
+        .split_whitespace()
+        .collect();
+
+    // starttime is field 22 of /proc/<pid>/stat, index 19 after the command
+    let start_time = stat_fields.get(19).copied().unwrap_or("0");
+
+    // session is field 6 and tty_nr field 7 of /proc/<pid>/stat
+    if let Some(session) = stat_fields.get(3) {
+        proc.session_id = Pid::try_from(*session).unwrap_or(Pid::new(0));
+    }
+    if let Some(tty_nr) = stat_fields.get(4) {
+        proc.tty_nr = tty_nr.parse().unwrap_or(0);
+    }
+
+    // supplementary groups, the Groups: line is empty when there are none
+    if let Some(groups_line) = lines.iter().find(|line| line.starts_with("Groups:")) {
+        for real_supplementary_gid in groups_line
+            .trim_start_matches("Groups:")
+            .split_whitespace()
+        {
+            let real_supplementary_gid = Gid::try_from(real_supplementary_gid)?;
+            proc.supplementary_gids
+                .push(gid_map.map_to_gid(real_supplementary_gid).unwrap());
+        }
+    }
+
+    let mut hasher = DefaultHasher::new();
+    start_time.hash(&mut hasher);
+    proc.real_pid.hash(&mut hasher);
+    proc.exec_path.hash(&mut hasher);
+    proc.process_uid = format!("{:016x}", hasher.finish());
+
+    // derive the logical service name when normalization rules are configured
+    proc.normalized_command = glob_conf.normalize_command(proc.command.trim());
+
+    // bound pathological string fields after the identity hash, so a
+    // truncated exec_path still yields the same process_uid
+    if let Some(max_string_len) = glob_conf.get_max_string_len() {
+        proc.strings_truncated |= truncate_with_marker(&mut proc.exec_path, max_string_len);
+        proc.strings_truncated |= truncate_with_marker(&mut proc.command, max_string_len);
+        if let Some(normalized_command) = &mut proc.normalized_command {
+            proc.strings_truncated |= truncate_with_marker(normalized_command, max_string_len);
+        }
+    }
+
+    // capture allow-listed env vars, unreadable environ just means no env
+    let capture_env_keys = glob_conf.get_capture_env_keys();
+    if !capture_env_keys.is_empty() {
+        let environ = fs::read(format!("/proc/{}/environ", proc.real_pid)).unwrap_or_default();
+        for entry in environ.split(|byte| *byte == 0) {
+            let entry = String::from_utf8_lossy(entry);
+            if let Some((key, value)) = entry.split_once('=') {
+                if capture_env_keys.iter().any(|allowed_key| allowed_key == key) {
+                    proc.env.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+    }
 
     // get memory usage
     let mem_data = fs::read_to_string(format!("/proc/{}/status", proc.real_pid))?;
     let mem_data: Vec<&str> = mem_data.lines().collect();
 
-    let (vss, rss, swap) = if glob_conf.is_old_kernel() {
-        (
-            mem_data[13].split_whitespace().collect::<Vec<&str>>()[1].parse::<usize>()?,
-            mem_data[17].split_whitespace().collect::<Vec<&str>>()[1].parse::<usize>()?,
-            mem_data[26].split_whitespace().collect::<Vec<&str>>()[1].parse::<usize>()?,
-        )
-    } else {
-        (
-            mem_data[17].split_whitespace().collect::<Vec<&str>>()[1].parse::<usize>()?,
-            mem_data[21].split_whitespace().collect::<Vec<&str>>()[1].parse::<usize>()?,
-            mem_data[30].split_whitespace().collect::<Vec<&str>>()[1].parse::<usize>()?,
-        )
+    // the Vm* lines are absent for kernel threads, which simply use no
+    // user-space memory
+    let mem_kb = |key: &str| -> usize {
+        status_value(&mem_data, key)
+            .and_then(|value| value.split_whitespace().next())
+            .and_then(|kb| kb.parse().ok())
+            .unwrap_or(0)
+    };
+
+    let vss = mem_kb("VmSize:");
+    let swap = mem_kb("VmSwap:");
+
+    // pss divides shared pages proportionally so tree sums don't overcount
+    // them, at the price of the slower smaps_rollup read; a missing rollup
+    // (old kernel, no permission) falls back to plain rss
+    let rss = match glob_conf.get_memory_accounting() {
+        setting::MemoryAccounting::Sum => mem_kb("VmRSS:"),
+        setting::MemoryAccounting::Pss => {
+            match fs::read_to_string(format!("/proc/{}/smaps_rollup", proc.real_pid)) {
+                Ok(rollup_content) => {
+                    let rollup_lines: Vec<&str> = rollup_content.lines().collect();
+                    status_value(&rollup_lines, "Pss:")
+                        .and_then(|value| value.split_whitespace().next())
+                        .and_then(|kb| kb.parse().ok())
+                        .unwrap_or_else(|| mem_kb("VmRSS:"))
+                }
+                Err(_) => mem_kb("VmRSS:"),
+            }
+        }
     };
     proc.stat.total_vss += DataCount::from_kb(vss);
     proc.stat.total_rss += DataCount::from_kb(rss);
@@ -1242,153 +2257,416 @@ pub fn get_real_proc(
         if let Ok(link) = fd.path().read_link() {
             let link = link.as_path().to_str().unwrap();
             if link.len() > 9 && &link[0..8] == "socket:[" {
-                inodes.push(Inode::try_from(&link[8..link.len() - 1]).unwrap());
+                inodes.push(Inode::try_from(&link[8..link.len() - 1])?);
             }
         }
     }
 
+    proc.socket_count = inodes.len();
+
+    // resolve the ports this process listens on, deduped and sorted
+    for inode in &inodes {
+        if let Some(port) = net_rawstat.lookup_listening_port(inode) {
+            proc.listening_ports.push(port);
+        }
+    }
+    proc.listening_ports.sort_unstable();
+    proc.listening_ports.dedup();
+
     // match inode to uniconnection stat
     for inode in inodes {
-        if let Some(connection) = net_rawstat.lookup_connection(&inode) {
-            let connection = connection.clone();
-
-            if let Some(iname) = net_rawstat.lookup_interface_name(&connection) {
-                let iname = iname.to_string();
-
-                let uni_conn = UniConnection::new(
-                    connection.get_local_addr(),
-                    connection.get_local_port(),
-                    connection.get_remote_addr(),
-                    connection.get_remote_port(),
-                    connection.get_connection_type(),
-                );
-
-                let reverse_uni_conn = UniConnection::new(
-                    connection.get_remote_addr(),
-                    connection.get_remote_port(),
-                    connection.get_local_addr(),
-                    connection.get_local_port(),
-                    connection.get_connection_type(),
-                );
-
-                // get interface raw stats
-                if let Some(irawstat) = net_rawstat.get_irawstat(&iname) {
-                    // get 2 uniconnection stats from interface raw stat
-                    let uni_conn_stat = irawstat
-                        .get_uni_connection_stat(&uni_conn)
-                        .unwrap_or(&UniConnectionStat::new(uni_conn))
-                        .clone();
-
-                    let reverse_uni_conn_stat = irawstat
-                        .get_uni_connection_stat(&reverse_uni_conn)
-                        .unwrap_or(&UniConnectionStat::new(reverse_uni_conn))
-                        .clone();
-
-                    // make new connection stat
-                    let mut conn_stat = ConnectionStat::new(connection.clone());
-
-                    conn_stat.pack_sent = uni_conn_stat.get_packet_count();
-                    conn_stat.pack_recv = reverse_uni_conn_stat.get_packet_count();
-
-                    conn_stat.total_data_sent = uni_conn_stat.get_total_data_count();
-                    conn_stat.total_data_recv = reverse_uni_conn_stat.get_total_data_count();
-
-                    conn_stat.real_data_sent = uni_conn_stat.get_real_data_count();
-                    conn_stat.real_data_recv = reverse_uni_conn_stat.get_real_data_count();
-
-                    // add new connection stat to interface stat
-                    proc.stat.netstat.add_connection_stat(&iname, conn_stat);
-                }
+        if let Some(connection) = net_rawstat.lookup_connection(&inode).cloned() {
+            // an inherited socket shares its inode across the fork family;
+            // the first process to claim it owns the attribution, which is
+            // the creating parent given the parent-first tree walk. later
+            // holders still count the socket but add no connection stats
+            if !net_rawstat.claim_inode(&inode, proc.real_pid) {
+                continue;
+            }
+            proc.attributed_socket_count += 1;
+
+            // remotes outside the configured cidr ranges keep counting
+            // toward interface totals but get no per-connection detail
+            if !glob_conf.connection_address_included(&connection.get_remote_addr()) {
+                continue;
             }
+
+            // the index precomputed the interface walk and the stat merge;
+            // taking the entry marks its uniconnection stats used
+            if let Some(entry) = net_rawstat.take_connection_stat(&inode) {
+                let (iname, conn_stat) = entry.into_parts();
+                proc.stat.netstat.add_connection_stat(&iname, conn_stat);
+            }
+        } else if net_rawstat.is_unsupported_protocol(&inode) {
+            // sctp/udplite/raw sockets have no connection entry; count them
+            // instead of dropping them silently
+            proc.unsupported_socket_count += 1;
         }
     }
 
-    // update threads list
-    let task_dir = match fs::read_dir(format!("/proc/{}/task", proc.real_pid)) {
-        Ok(dir) => dir,
-        Err(err) => return Err(ProcessError::IOErr(err)),
-    };
+    // in process granularity one TGID-level query stands in for the whole
+    // per-thread walk, trading thread detail for a single netlink round trip
+    let taskstats_granularity = glob_conf.get_taskstats_granularity();
 
-    for thread_dir in task_dir {
-        let thread_dir = thread_dir.unwrap();
-
-        if thread_dir.file_type().unwrap().is_dir() {
-            if let Ok(real_tid) = Tid::try_from(thread_dir.file_name().to_str().unwrap()) {
-                // get tid
-                let thread_status_file_content = match fs::read_to_string(format!(
-                    "{}/status",
-                    thread_dir.path().to_str().unwrap()
-                )) {
-                    Ok(content) => content,
-                    Err(_) => continue,
-                };
-
-                let thread_lines: Vec<&str> = thread_status_file_content.lines().collect();
-
-                // get tid
-                let tid = if glob_conf.is_old_kernel() {
-                    Tid::new(0)
-                } else {
-                    let tids = thread_lines[13].split_whitespace().collect::<Vec<&str>>();
-                    Tid::try_from(tids[tids.len() - 1]).unwrap()
-                };
-
-                let mut new_thread = Thread::new(tid, proc.pid, real_tid, proc.real_pid);
+    if taskstats_granularity == setting::TaskstatsGranularity::Process {
+        apply_process_taskstats(&mut proc, taskstats_conn);
+    } else {
+        // update threads list
+        let task_dir = match fs::read_dir(format!("/proc/{}/task", proc.real_pid)) {
+            Ok(dir) => dir,
+            Err(err) => return Err(ProcessError::IOErr(err)),
+        };
 
-                if let Ok(thread_stat) = new_thread.get_stat(taskstats_conn) {
-                    proc.stat += thread_stat;
+        let mut threads_stat_source: Option<StatSource> = None;
+
+        for thread_dir in task_dir {
+            let thread_dir = thread_dir.unwrap();
+
+            if thread_dir.file_type().unwrap().is_dir() {
+                if let Ok(real_tid) = Tid::try_from(thread_dir.file_name().to_str().unwrap()) {
+                    // get tid
+                    let thread_status_file_content = match fs::read_to_string(format!(
+                        "{}/status",
+                        thread_dir.path().to_str().unwrap()
+                    )) {
+                        Ok(content) => content,
+                        Err(_) => continue,
+                    };
+
+                    let thread_lines: Vec<&str> = thread_status_file_content.lines().collect();
+
+                    // get tid, falling back to the real tid without pid namespaces
+                    let tid = if glob_conf.is_old_kernel() {
+                        Tid::new(0)
+                    } else {
+                        match status_value(&thread_lines, "NSpid:")
+                            .and_then(|values| values.split_whitespace().last())
+                        {
+                            Some(tid) => Tid::try_from(tid)?,
+                            None => real_tid,
+                        }
+                    };
+
+                    let mut new_thread = Thread::new(tid, proc.pid, real_tid, proc.real_pid);
+
+                    if let Ok(thread_stat) = new_thread.get_stat(taskstats_conn) {
+                        threads_stat_source = Some(match threads_stat_source {
+                            Some(stat_source) => stat_source.combine(thread_stat.get_stat_source()),
+                            None => thread_stat.get_stat_source(),
+                        });
+
+                        // process totals are the per-thread stats summed once; the
+                        // TGID-level taskstats query is deliberately never mixed in
+                        // here as that would double count the threads
+                        proc.stat += thread_stat;
+
+                        // last-run cpu is field 39 of the task stat file, skip if the
+                        // thread exited meanwhile
+                        if let Ok(task_stat_content) = fs::read_to_string(format!(
+                            "{}/stat",
+                            thread_dir.path().to_str().unwrap()
+                        )) {
+                            let task_stat_fields: Vec<&str> = task_stat_content
+                                .rsplit(')')
+                                .next()
+                                .unwrap_or("")
+                                .split_whitespace()
+                                .collect();
+
+                            if let Some(processor) = task_stat_fields.get(36) {
+                                new_thread.stat.last_cpu = processor.parse().unwrap_or(0);
+                            }
+                        }
+
+                        // affinity list from the thread status we already read
+                        for thread_line in &thread_lines {
+                            if let Some(list) = thread_line.strip_prefix("Cpus_allowed_list:") {
+                                new_thread.stat.cpu_affinity = list.trim().to_string();
+                            }
+                        }
+
+                        // add new thread
+                        proc.threads.push(new_thread);
+                    }
+                }
+            }
+        }
 
-                    // add new thread
-                    proc.threads.push(new_thread);
+        // the process source reflects what its threads were collected with
+        if let Some(stat_source) = threads_stat_source {
+            proc.stat.set_stat_source(stat_source);
+        }
+    }
+    // update child list. children is a per-thread file and any thread can
+    // fork, so reading only task/<pid>/children misses children of other
+    // threads (and everything when the main thread already exited). walk
+    // the actual task subdirectories and union their children files
+    if let Ok(task_dirs) = fs::read_dir(format!("/proc/{}/task", proc.real_pid)) {
+        for task_dir in task_dirs.flatten() {
+            let children_list = match fs::read_to_string(format!(
+                "/proc/{}/task/{}/children",
+                proc.real_pid,
+                task_dir.file_name().to_string_lossy()
+            )) {
+                Ok(list) => list,
+                // the thread may have exited between the listing and the read
+                Err(_) => continue,
+            };
+
+            for child_real_pid in children_list.split_terminator(" ") {
+                let child_real_pid = Pid(child_real_pid.parse::<u128>().unwrap());
+                if !proc.child_real_pid_list.contains(&child_real_pid) {
+                    proc.child_real_pid_list.push(child_real_pid);
                 }
             }
         }
     }
-    // update child list
-    let children_list = match fs::read_to_string(format!(
-        "/proc/{}/task/{}/children",
-        proc.real_pid, proc.real_pid
-    )) {
-        Ok(list) => list,
-        Err(_) => "".to_owned(),
+    proc.child_count = proc.child_real_pid_list.len();
+
+    Ok(proc)
+}
+
+// fill the cpu/io counters from the TGID-level taskstats aggregate, the
+// process-granularity stand-in for summing per-thread queries. memory and
+// network numbers are per-process already and stay untouched
+fn apply_process_taskstats(proc: &mut Process, taskstats_conn: &dyn TaskStatsSource) {
+    let process_taskstats = match taskstats_conn.process_taskstats(proc.real_pid) {
+        Ok(process_taskstats) => process_taskstats,
+        // nothing earlier in this mode touches the cpu counters, so without a
+        // fallback the sample would report zero cpu attributed to taskstats.
+        // fill the cpu times from /proc and mark the sample accordingly
+        Err(_) => {
+            apply_process_proc_cpu(proc);
+            return;
+        }
     };
 
-    for child_real_pid in children_list.split_terminator(" ") {
-        proc.child_real_pid_list
-            .push(Pid(child_real_pid.parse::<u128>().unwrap()))
-    }
+    proc.stat.total_system_cpu_time = process_taskstats.system_cpu_time;
+    proc.stat.total_user_cpu_time = process_taskstats.user_cpu_time;
+    proc.stat.total_cpu_time = process_taskstats.system_cpu_time + process_taskstats.user_cpu_time;
 
-    Ok(proc)
+    proc.stat.total_io_read = process_taskstats.io_read;
+    proc.stat.total_io_write = process_taskstats.io_write;
+
+    proc.stat.total_block_io_read = process_taskstats.block_io_read;
+    proc.stat.total_block_io_write = process_taskstats.block_io_write;
+
+    proc.stat.minor_faults = process_taskstats.minor_fault_count;
+    proc.stat.major_faults = process_taskstats.major_fault_count;
+
+    proc.stat.cpu_delay_total = process_taskstats.cpu_delay_total;
+    proc.stat.cpu_delay_count = process_taskstats.cpu_delay_count;
+    proc.stat.block_io_delay_total = process_taskstats.block_io_delay_total;
+    proc.stat.block_io_delay_count = process_taskstats.block_io_delay_count;
+    proc.stat.swapin_delay_total = process_taskstats.swapin_delay_total;
+    proc.stat.swapin_delay_count = process_taskstats.swapin_delay_count;
+
+    proc.stat.peak_rss = process_taskstats.high_water_rss;
+    proc.stat.peak_vss = process_taskstats.high_water_vss;
+
+    proc.stat.stat_source = StatSource::Taskstats;
+}
+
+// cpu times from /proc/<pid>/stat fields 14/15, the process-granularity
+// counterpart of Thread::get_stat_from_proc. the io counters stay zero
+fn apply_process_proc_cpu(proc: &mut Process) {
+    let stat_content = match fs::read_to_string(format!("/proc/{}/stat", proc.real_pid)) {
+        Ok(stat_content) => stat_content,
+        Err(_) => return,
+    };
+    let stat_fields: Vec<&str> = stat_content
+        .rsplit(')')
+        .next()
+        .unwrap_or("")
+        .split_whitespace()
+        .collect();
+
+    // utime and stime are fields 14/15, index 11/12 after the command
+    let utime_ticks: usize = stat_fields
+        .get(11)
+        .and_then(|ticks| ticks.parse().ok())
+        .unwrap_or(0);
+    let stime_ticks: usize = stat_fields
+        .get(12)
+        .and_then(|ticks| ticks.parse().ok())
+        .unwrap_or(0);
+
+    let ticks_per_second = clock_ticks_per_second();
+    proc.stat.total_user_cpu_time = ticks_to_time(utime_ticks, ticks_per_second);
+    proc.stat.total_system_cpu_time = ticks_to_time(stime_ticks, ticks_per_second);
+    proc.stat.total_cpu_time = proc.stat.total_user_cpu_time + proc.stat.total_system_cpu_time;
+
+    proc.stat.stat_source = StatSource::Proc;
+}
+
+// hard stop for pathological children data, overridable via max_tree_depth
+const DEFAULT_MAX_TREE_DEPTH: usize = 64;
+
+fn max_tree_depth() -> usize {
+    setting::get_glob_conf()
+        .map(|conf| conf.read().unwrap().get_max_tree_depth())
+        .unwrap_or(None)
+        .unwrap_or(DEFAULT_MAX_TREE_DEPTH)
 }
 
 pub fn iterate_proc_tree(
     root_proc: &Process,
     processes_list: &mut Vec<Process>,
     iterated_pids: &mut Vec<Pid>,
-    taskstats_conn: &TaskStatsConnection,
+    taskstats_conn: &dyn TaskStatsSource,
     net_rawstat: &mut NetworkRawStat,
 ) {
-    let mut procs_stack: Vec<Process> = Vec::new();
-    procs_stack.push(root_proc.clone());
-
-    let mut temp: Process;
+    let depth_cap = max_tree_depth();
+    let walk_start = processes_list.len();
 
-    while !procs_stack.is_empty() {
-        temp = procs_stack.pop().unwrap();
+    let mut procs_stack: Vec<(Process, usize)> = Vec::new();
+    iterated_pids.push(root_proc.real_pid);
+    procs_stack.push((root_proc.clone(), 0));
 
+    while let Some((temp, depth)) = procs_stack.pop() {
         // Push data of a process here
         processes_list.push(temp.clone());
-        iterated_pids.push(temp.real_pid);
+
+        // racy /proc reads and pid reuse could in theory produce cyclic or
+        // absurdly deep children data, the cap guarantees termination
+        if depth >= depth_cap {
+            continue;
+        }
+
+        for child_real_pid in &temp.child_real_pid_list {
+            if iterated_pids.contains(child_real_pid) {
+                continue;
+            }
+            // mark before enqueueing so two parents listing the same child
+            // can't both push it
+            iterated_pids.push(*child_real_pid);
+
+            if let Ok(child_proc) = get_real_proc(child_real_pid, taskstats_conn, net_rawstat) {
+                procs_stack.push((child_proc, depth + 1))
+            }
+        }
+    }
+
+    fill_descendant_counts(&mut processes_list[walk_start..]);
+}
+
+// descendant_count covers exactly the subtree the walk emitted, so pids
+// claimed by an earlier walk or cut off by the depth cap are not counted
+fn fill_descendant_counts(walked: &mut [Process]) {
+    let index_by_pid: HashMap<Pid, usize> = walked
+        .iter()
+        .enumerate()
+        .map(|(index, proc)| (proc.real_pid, index))
+        .collect();
+
+    // the stack walk emits every child after its parent, so a reverse pass
+    // has each child's count ready before its parent sums them
+    for index in (0..walked.len()).rev() {
+        let mut descendant_count = 0;
+        for child_real_pid in walked[index].child_real_pid_list.clone() {
+            if let Some(&child_index) = index_by_pid.get(&child_real_pid) {
+                if child_index > index {
+                    descendant_count += 1 + walked[child_index].descendant_count;
+                }
+            }
+        }
+        walked[index].descendant_count = descendant_count;
+    }
+}
+
+// like iterate_proc_tree, but descendants are summed into the root's
+// accumulated stat instead of being emitted individually
+pub fn iterate_proc_tree_roots_only(
+    root_proc: &Process,
+    processes_list: &mut Vec<Process>,
+    iterated_pids: &mut Vec<Pid>,
+    taskstats_conn: &dyn TaskStatsSource,
+    net_rawstat: &mut NetworkRawStat,
+) {
+    let depth_cap = max_tree_depth();
+
+    let mut root = root_proc.clone();
+
+    let mut procs_stack: Vec<(Process, usize)> = Vec::new();
+    iterated_pids.push(root_proc.real_pid);
+    procs_stack.push((root_proc.clone(), 0));
+
+    while let Some((temp, depth)) = procs_stack.pop() {
+        // fold descendant stats into the root here; each folded process is
+        // one descendant, counted once thanks to the iterated_pids guard
+        if temp.real_pid != root.real_pid {
+            root.stat += temp.stat.clone();
+            root.descendant_count += 1;
+        }
+
+        // same termination guard as iterate_proc_tree
+        if depth >= depth_cap {
+            continue;
+        }
 
         for child_real_pid in &temp.child_real_pid_list {
             if iterated_pids.contains(child_real_pid) {
                 continue;
             }
+            // mark before enqueueing so two parents listing the same child
+            // can't both push it
+            iterated_pids.push(*child_real_pid);
+
             if let Ok(child_proc) = get_real_proc(child_real_pid, taskstats_conn, net_rawstat) {
-                procs_stack.push(child_proc)
+                procs_stack.push((child_proc, depth + 1))
             }
         }
     }
+
+    processes_list.push(root);
+}
+
+// describes the semantics of every serialized stat field so consumers can
+// tell cumulative-since-start counters apart from instantaneous gauges;
+// time fields carry nanoseconds, matching TimeCount's serialization
+pub fn stat_semantics() -> serde_json::Value {
+    serde_json::json!({
+        "process_stat": {
+            "timestamp": { "kind": "gauge", "unit": "nanosecond" },
+            "total_system_cpu_time": { "kind": "cumulative", "unit": "nanosecond" },
+            "total_user_cpu_time": { "kind": "cumulative", "unit": "nanosecond" },
+            "total_cpu_time": { "kind": "cumulative", "unit": "nanosecond" },
+            "total_rss": { "kind": "gauge", "unit": "byte" },
+            "total_vss": { "kind": "gauge", "unit": "byte" },
+            "total_swap": { "kind": "gauge", "unit": "byte" },
+            "peak_rss": { "kind": "gauge", "unit": "byte" },
+            "peak_vss": { "kind": "gauge", "unit": "byte" },
+            "total_io_read": { "kind": "cumulative", "unit": "byte" },
+            "total_io_write": { "kind": "cumulative", "unit": "byte" },
+            "total_block_io_read": { "kind": "cumulative", "unit": "byte" },
+            "total_block_io_write": { "kind": "cumulative", "unit": "byte" },
+            "minor_faults": { "kind": "cumulative", "unit": "fault" },
+            "major_faults": { "kind": "cumulative", "unit": "fault" },
+            "cpu_delay_avg": { "kind": "gauge", "unit": "nanosecond" },
+            "block_io_delay_avg": { "kind": "gauge", "unit": "nanosecond" },
+            "swapin_delay_avg": { "kind": "gauge", "unit": "nanosecond" },
+        },
+        "thread_stat": {
+            "timestamp": { "kind": "gauge", "unit": "nanosecond" },
+            "total_system_cpu_time": { "kind": "cumulative", "unit": "nanosecond" },
+            "total_user_cpu_time": { "kind": "cumulative", "unit": "nanosecond" },
+            "total_cpu_time": { "kind": "cumulative", "unit": "nanosecond" },
+            "total_io_read": { "kind": "cumulative", "unit": "byte" },
+            "total_io_write": { "kind": "cumulative", "unit": "byte" },
+            "total_block_io_read": { "kind": "cumulative", "unit": "byte" },
+            "total_block_io_write": { "kind": "cumulative", "unit": "byte" },
+            "minor_faults": { "kind": "cumulative", "unit": "fault" },
+            "major_faults": { "kind": "cumulative", "unit": "fault" },
+        },
+        "connection_stat": {
+            "pack_sent": { "kind": "cumulative", "unit": "packet" },
+            "pack_recv": { "kind": "cumulative", "unit": "packet" },
+            "total_data_sent": { "kind": "cumulative", "unit": "byte" },
+            "total_data_recv": { "kind": "cumulative", "unit": "byte" },
+            "real_data_sent": { "kind": "cumulative", "unit": "byte" },
+            "real_data_recv": { "kind": "cumulative", "unit": "byte" },
+        },
+    })
 }
 
 #[derive(Debug)]
@@ -1399,6 +2677,9 @@ pub enum ProcessError {
     UIDMapErr,
     GIDMapErr,
     CommonErr(CommonError),
+    // not a failure: the pid is a kernel thread and include_kernel_threads
+    // is off, so the caller should drop it without recording an error
+    KernelThreadSkipped(Pid),
 }
 
 impl std::error::Error for ProcessError {}
@@ -1412,6 +2693,9 @@ impl fmt::Display for ProcessError {
             Self::UIDMapErr => String::from(format!("Uid map error")),
             Self::GIDMapErr => String::from(format!("Gid map error")),
             Self::CommonErr(error) => String::from(format!("Common error: {}", error)),
+            Self::KernelThreadSkipped(real_pid) => {
+                String::from(format!("Kernel thread skipped: {}", real_pid))
+            }
         };
 
         write!(f, "{}", result)
@@ -1441,3 +2725,440 @@ impl From<CommonError> for ProcessError {
         Self::CommonErr(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taskstat::TaskStats;
+
+    #[test]
+    fn status_value_looks_up_keys_by_name() {
+        let lines = ["Name:\tnginx", "NSpid:\t4242\t1", "Uid:\t0\t0\t0\t0"];
+
+        assert_eq!(status_value(&lines, "Name:"), Some("nginx"));
+        assert_eq!(status_value(&lines, "NSpid:"), Some("4242\t1"));
+        // absent keys yield None instead of a line-index panic
+        assert_eq!(status_value(&lines, "VmRSS:"), None);
+    }
+
+    #[test]
+    fn cpu_percent_scales_and_clamps() {
+        // half the interval spent on cpu is 50 percent of one cpu
+        let half = cpu_percent_over_interval(TimeCount::from_secs(1), 2_000_000_000).unwrap();
+        assert!((half - 50.0).abs() < f64::EPSILON);
+
+        // a zero interval can't be rated
+        assert_eq!(cpu_percent_over_interval(TimeCount::from_secs(1), 0), None);
+
+        // more cpu time than cpus could provide clamps to the online count
+        let num_cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let clamped =
+            cpu_percent_over_interval(TimeCount::from_secs(10_000), 1_000_000_000).unwrap();
+        assert!((clamped - 100.0 * num_cpus as f64).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn empty_uid_map_is_the_identity_mapping() {
+        let uid_map = UidMap::new();
+        assert_eq!(uid_map.map_to_uid(Uid::new(1000)), Some(Uid::new(1000)));
+    }
+
+    #[test]
+    fn uid_map_entries_translate_their_range() {
+        let uid_map = UidMap::try_from("0 100000 65536\n").unwrap();
+
+        assert_eq!(
+            uid_map.map_to_uid(Uid::new(100000)),
+            Some(Uid::new(0))
+        );
+        assert_eq!(
+            uid_map.map_to_uid(Uid::new(101000)),
+            Some(Uid::new(1000))
+        );
+        // outside every entry there is no mapping
+        assert_eq!(uid_map.map_to_uid(Uid::new(5)), None);
+    }
+
+    #[test]
+    fn stat_semantics_describes_the_known_fields() {
+        let semantics = stat_semantics();
+
+        assert_eq!(
+            semantics["process_stat"]["total_cpu_time"],
+            serde_json::json!({ "kind": "cumulative", "unit": "nanosecond" })
+        );
+        assert_eq!(
+            semantics["process_stat"]["total_rss"],
+            serde_json::json!({ "kind": "gauge", "unit": "byte" })
+        );
+        assert_eq!(
+            semantics["thread_stat"]["major_faults"]["kind"],
+            "cumulative"
+        );
+        assert_eq!(
+            semantics["connection_stat"]["total_data_sent"]["unit"],
+            "byte"
+        );
+    }
+
+    // a source whose netlink transport is permanently down
+    struct FailingSource;
+
+    impl TaskStatsSource for FailingSource {
+        fn thread_taskstats(&self, _real_tid: Tid) -> Result<TaskStats, TaskStatsError> {
+            Err(TaskStatsError::GetFamilyIdErr)
+        }
+
+        fn process_taskstats(&self, _real_pid: Pid) -> Result<TaskStats, TaskStatsError> {
+            Err(TaskStatsError::GetFamilyIdErr)
+        }
+    }
+
+    #[test]
+    fn thread_stat_reports_proc_source_when_taskstats_fails() {
+        // point the thread at ourselves so the /proc fallback has a real
+        // stat file to read
+        let own_pid = std::process::id() as usize;
+        let mut thread = Thread::new(
+            Tid::new(own_pid),
+            Pid::new(own_pid),
+            Tid::new(own_pid),
+            Pid::new(own_pid),
+        );
+
+        let stat = thread.get_stat(&FailingSource).unwrap();
+
+        // /proc filled in the cpu times, so the sample must not claim taskstats
+        assert_eq!(stat.get_stat_source(), StatSource::Proc);
+    }
+
+    // a source that hands back one fixed taskstats sample for any query
+    struct FixedSource(TaskStats);
+
+    impl TaskStatsSource for FixedSource {
+        fn thread_taskstats(&self, _real_tid: Tid) -> Result<TaskStats, TaskStatsError> {
+            Ok(self.0.clone())
+        }
+
+        fn process_taskstats(&self, _real_pid: Pid) -> Result<TaskStats, TaskStatsError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn stub_taskstats() -> TaskStats {
+        TaskStats {
+            command_str: String::from("stub"),
+            pid: Pid::new(1),
+            uid: Uid::new(0),
+            gid: Gid::new(0),
+            parent_pid: Pid::new(1),
+            nice: 0,
+            flags: 0,
+            exitcode: 0,
+            timestamp: Timestamp::new(),
+            begin_time: UNIX_EPOCH,
+            elapsed_time: TimeCount::from_secs(1),
+            scheduling_discipline: 0,
+            user_cpu_time: TimeCount::from_nanosecs(111),
+            system_cpu_time: TimeCount::from_nanosecs(222),
+            accumulated_rss: DataCount::from_byte(0),
+            accumulated_vss: DataCount::from_byte(0),
+            high_water_rss: DataCount::from_byte(333),
+            high_water_vss: DataCount::from_byte(444),
+            io_read: DataCount::from_byte(555),
+            io_write: DataCount::from_byte(666),
+            read_syscall_count: Count::new(0),
+            write_syscall_count: Count::new(0),
+            block_io_read: DataCount::from_byte(777),
+            block_io_write: DataCount::from_byte(888),
+            cancelled_block_io_write: DataCount::from_byte(0),
+            cpu_delay_count: Count::new(9),
+            cpu_delay_total: TimeCount::from_nanosecs(999),
+            minor_fault_count: Count::new(10),
+            major_fault_count: Count::new(11),
+            free_pages_delay_count: Count::new(0),
+            free_pages_delay_total: TimeCount::new(),
+            thrashing_delay_count: Count::new(0),
+            thrashing_delay_total: TimeCount::new(),
+            block_io_delay_count: Count::new(12),
+            block_io_delay_total: TimeCount::from_nanosecs(1212),
+            swapin_delay_count: Count::new(13),
+            swapin_delay_total: TimeCount::from_nanosecs(1313),
+            memory_compact_delay_count: Count::new(0),
+            memory_compact_delay_total: TimeCount::new(),
+            voluntary_context_switches: Count::new(0),
+            nonvoluntary_context_switches: Count::new(0),
+            cpu_runtime_real_total: TimeCount::new(),
+            cpu_runtime_virtual_total: TimeCount::new(),
+            user_time_scaled: TimeCount::new(),
+            system_time_scaled: TimeCount::new(),
+            run_real_total_scaled: TimeCount::new(),
+        }
+    }
+
+    fn test_process(real_pid: usize) -> Process {
+        Process::new(
+            Pid::new(real_pid),
+            Pid::new(1),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Pid::new(real_pid),
+            Pid::new(1),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Uid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            Gid::new(0),
+            String::from("/bin/stub"),
+            String::from("stub"),
+        )
+    }
+
+    #[test]
+    fn process_granularity_copies_the_taskstats_aggregate() {
+        let mut proc = test_process(1);
+
+        apply_process_taskstats(&mut proc, &FixedSource(stub_taskstats()));
+
+        assert_eq!(proc.stat.total_user_cpu_time, TimeCount::from_nanosecs(111));
+        assert_eq!(
+            proc.stat.total_system_cpu_time,
+            TimeCount::from_nanosecs(222)
+        );
+        assert_eq!(proc.stat.total_cpu_time, TimeCount::from_nanosecs(333));
+        assert_eq!(proc.stat.total_io_read, DataCount::from_byte(555));
+        assert_eq!(proc.stat.total_io_write, DataCount::from_byte(666));
+        assert_eq!(proc.stat.major_faults, Count::new(11));
+        assert_eq!(proc.stat.peak_rss, DataCount::from_byte(333));
+        assert_eq!(proc.stat.get_stat_source(), StatSource::Taskstats);
+    }
+
+    #[test]
+    fn process_granularity_falls_back_to_proc_cpu_when_taskstats_fails() {
+        let own_pid = std::process::id() as usize;
+        let mut proc = test_process(own_pid);
+
+        apply_process_taskstats(&mut proc, &FailingSource);
+
+        // the sample no longer claims taskstats fidelity it never had
+        assert_eq!(proc.stat.get_stat_source(), StatSource::Proc);
+        assert_eq!(
+            proc.stat.total_cpu_time,
+            proc.stat.total_user_cpu_time + proc.stat.total_system_cpu_time
+        );
+    }
+
+    #[test]
+    fn thread_granularity_copies_the_per_thread_taskstats() {
+        let mut thread = Thread::new(Tid::new(1), Pid::new(1), Tid::new(1), Pid::new(1));
+
+        let stat = thread.get_stat(&FixedSource(stub_taskstats())).unwrap();
+
+        assert_eq!(stat.total_user_cpu_time, TimeCount::from_nanosecs(111));
+        assert_eq!(stat.total_cpu_time, TimeCount::from_nanosecs(333));
+        assert_eq!(stat.total_block_io_read, DataCount::from_byte(777));
+        assert_eq!(stat.swapin_delay_count, Count::new(13));
+        assert_eq!(stat.get_stat_source(), StatSource::Taskstats);
+    }
+
+    #[test]
+    fn two_threads_sum_into_the_process_total_exactly_once() {
+        // the group leader and a worker, each answered by its own per-thread
+        // (PID attr) taskstats sample, never the TGID aggregate
+        let mut leader = Thread::new(Tid::new(1), Pid::new(1), Tid::new(1), Pid::new(1));
+        let leader_stat = leader.get_stat(&FixedSource(stub_taskstats())).unwrap();
+
+        let mut worker_taskstats = stub_taskstats();
+        worker_taskstats.user_cpu_time = TimeCount::from_nanosecs(1000);
+        worker_taskstats.system_cpu_time = TimeCount::from_nanosecs(2000);
+        worker_taskstats.io_read = DataCount::from_byte(5000);
+        let mut worker = Thread::new(Tid::new(2), Pid::new(1), Tid::new(2), Pid::new(1));
+        let worker_stat = worker.get_stat(&FixedSource(worker_taskstats)).unwrap();
+
+        let mut proc_stat = ProcessStat::new();
+        proc_stat += leader_stat;
+        proc_stat += worker_stat;
+
+        // the process total is the per-thread sum, with neither thread (in
+        // particular the leader) counted twice
+        assert_eq!(
+            proc_stat.total_cpu_time,
+            TimeCount::from_nanosecs(333 + 3000)
+        );
+        assert_eq!(proc_stat.total_io_read, DataCount::from_byte(555 + 5000));
+    }
+
+    // a thread stat with every summed counter set to a multiple of `base`,
+    // so accumulation mistakes show up as the wrong multiple
+    fn thread_stat(base: usize) -> ThreadStat {
+        let mut stat = ThreadStat::new();
+
+        stat.total_user_cpu_time = TimeCount::from_nanosecs(base);
+        stat.total_system_cpu_time = TimeCount::from_nanosecs(2 * base);
+        stat.total_cpu_time = TimeCount::from_nanosecs(3 * base);
+
+        stat.total_io_read = DataCount::from_byte(4 * base);
+        stat.total_io_write = DataCount::from_byte(5 * base);
+        stat.total_block_io_read = DataCount::from_byte(6 * base);
+        stat.total_block_io_write = DataCount::from_byte(7 * base);
+
+        stat.minor_faults = Count::new(8 * base);
+        stat.major_faults = Count::new(9 * base);
+
+        stat.cpu_delay_total = TimeCount::from_nanosecs(10 * base);
+        stat.cpu_delay_count = Count::new(base);
+
+        stat.peak_rss = DataCount::from_byte(11 * base);
+        stat.peak_vss = DataCount::from_byte(12 * base);
+
+        stat
+    }
+
+    #[test]
+    fn thread_stats_sum_into_the_process_stat() {
+        let mut proc_stat = ProcessStat::new();
+
+        proc_stat += thread_stat(1);
+        proc_stat += thread_stat(10);
+
+        assert_eq!(proc_stat.total_user_cpu_time, TimeCount::from_nanosecs(11));
+        assert_eq!(
+            proc_stat.total_system_cpu_time,
+            TimeCount::from_nanosecs(22)
+        );
+        assert_eq!(proc_stat.total_cpu_time, TimeCount::from_nanosecs(33));
+        assert_eq!(proc_stat.total_io_read, DataCount::from_byte(44));
+        assert_eq!(proc_stat.total_io_write, DataCount::from_byte(55));
+        assert_eq!(proc_stat.total_block_io_read, DataCount::from_byte(66));
+        assert_eq!(proc_stat.total_block_io_write, DataCount::from_byte(77));
+        assert_eq!(proc_stat.minor_faults, Count::new(88));
+        assert_eq!(proc_stat.major_faults, Count::new(99));
+
+        // peaks take the largest thread's value, not the sum
+        assert_eq!(proc_stat.peak_rss, DataCount::from_byte(110));
+        assert_eq!(proc_stat.peak_vss, DataCount::from_byte(120));
+
+        // the delay average is recomputed from the merged totals and counts
+        assert_eq!(proc_stat.cpu_delay_total, TimeCount::from_nanosecs(110));
+        assert_eq!(proc_stat.cpu_delay_count, Count::new(11));
+        assert_eq!(proc_stat.cpu_delay_avg, TimeCount::from_nanosecs(10));
+    }
+
+    #[test]
+    fn accumulation_counts_each_thread_exactly_once() {
+        // three identical threads must contribute exactly three times one
+        // thread's numbers — a leader double count would show as more
+        let mut proc_stat = ProcessStat::new();
+
+        for _ in 0..3 {
+            proc_stat += thread_stat(1);
+        }
+
+        assert_eq!(proc_stat.total_cpu_time, TimeCount::from_nanosecs(9));
+        assert_eq!(proc_stat.total_io_read, DataCount::from_byte(12));
+        assert_eq!(proc_stat.minor_faults, Count::new(24));
+    }
+
+    #[test]
+    fn cyclic_children_data_terminates_the_walk() {
+        // a root whose children list points back at itself, the degenerate
+        // cycle that racy /proc reads and pid reuse could produce
+        let mut root = test_process(3_999_999_999);
+        root.child_real_pid_list = vec![Pid::new(3_999_999_999), Pid::new(3_999_999_998)];
+
+        let mut processes_list = Vec::new();
+        let mut iterated_pids = Vec::new();
+        let mut net_rawstat = NetworkRawStat::new();
+        iterate_proc_tree(
+            &root,
+            &mut processes_list,
+            &mut iterated_pids,
+            &FailingSource,
+            &mut net_rawstat,
+        );
+
+        // the walk terminated and emitted the root exactly once; the
+        // nonexistent second child was dropped, not retried forever
+        assert_eq!(processes_list.len(), 1);
+        assert_eq!(processes_list[0].real_pid, Pid::new(3_999_999_999));
+    }
+
+    #[test]
+    fn ticks_convert_to_time_at_a_fixed_clock_rate() {
+        // at the usual 100hz every tick is 10ms
+        assert_eq!(
+            ticks_to_time(1, 100),
+            TimeCount::from_nanosecs(10_000_000)
+        );
+        assert_eq!(ticks_to_time(250, 100), TimeCount::from_milisecs(2_500));
+
+        // a 250hz kernel yields 4ms ticks
+        assert_eq!(ticks_to_time(3, 250), TimeCount::from_milisecs(12));
+
+        assert_eq!(ticks_to_time(0, 100), TimeCount::from_secs(0));
+    }
+
+    #[test]
+    fn deltas_report_the_movement_since_the_previous_sample() {
+        let mut prev = ProcessStat::new();
+        prev += thread_stat(1);
+        let mut curr = ProcessStat::new();
+        curr += thread_stat(10);
+
+        curr.compute_deltas(Some(&prev));
+        let deltas = curr.deltas.as_ref().unwrap();
+        assert_eq!(deltas.total_cpu_time_delta, TimeCount::from_nanosecs(27));
+        assert_eq!(deltas.total_io_read_delta, DataCount::from_byte(36));
+
+        // a process that just appeared reports its full value as the delta
+        let mut appeared = ProcessStat::new();
+        appeared += thread_stat(1);
+        appeared.compute_deltas(None);
+        assert_eq!(
+            appeared.deltas.as_ref().unwrap().total_cpu_time_delta,
+            TimeCount::from_nanosecs(3)
+        );
+
+        // a counter reset clamps the delta to zero instead of underflowing
+        let mut reset = ProcessStat::new();
+        reset += thread_stat(1);
+        reset.compute_deltas(Some(&curr));
+        assert_eq!(
+            reset.deltas.as_ref().unwrap().total_cpu_time_delta,
+            TimeCount::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn accumulated_timestamp_takes_the_max() {
+        let epoch = Timestamp::new();
+        let now = Timestamp::get_curr_timestamp();
+
+        // a newer thread sample moves the process timestamp forward
+        let mut proc_stat = ProcessStat::new();
+        proc_stat.timestamp = epoch;
+        let mut newer = ThreadStat::new();
+        newer.timestamp = now;
+        proc_stat += newer;
+        assert_eq!(proc_stat.timestamp, now);
+
+        // a stale thread sample must not move it backward
+        let mut proc_stat = ProcessStat::new();
+        proc_stat.timestamp = now;
+        let mut stale = ThreadStat::new();
+        stale.timestamp = epoch;
+        proc_stat += stale;
+        assert_eq!(proc_stat.timestamp, now);
+    }
+}
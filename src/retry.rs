@@ -0,0 +1,206 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+// shared retry/backoff policy for the kafka, redis and netlink subsystems
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+
+    #[serde(default = "default_jitter")]
+    pub jitter: bool,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+fn default_base_delay_ms() -> u64 {
+    100
+}
+fn default_max_delay_ms() -> u64 {
+    5_000
+}
+fn default_jitter() -> bool {
+    true
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            base_delay_ms: default_base_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // exponential backoff capped at max_delay_ms, attempt counts from 0
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1u64 << attempt.min(16));
+        let mut delay_ms = exponential.min(self.max_delay_ms);
+
+        if self.jitter {
+            // cheap jitter from the clock, keeps at least half the delay
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos() as u64;
+            delay_ms = delay_ms / 2 + nanos % (delay_ms / 2 + 1);
+        }
+
+        Duration::from_millis(delay_ms)
+    }
+}
+
+// run an operation under a policy, returning the last error when attempts
+// run out. sleeps through tokio so a retrying caller yields its worker
+// thread instead of blocking it for up to max_delay_ms per attempt
+pub async fn retry<T, E, F: FnMut() -> Result<T, E>>(
+    policy: &RetryPolicy,
+    mut operation: F,
+) -> Result<T, E> {
+    let mut attempt = 0;
+
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                attempt += 1;
+                if attempt >= policy.max_attempts {
+                    return Err(err);
+                }
+                tokio::time::sleep(policy.delay_for_attempt(attempt - 1)).await;
+            }
+        }
+    }
+}
+
+// the same loop for synchronous contexts (one-shot cli paths, blocking
+// sinks); callers on the runtime should use the async variant or wrap
+// themselves in block_in_place
+pub fn retry_blocking<T, E, F: FnMut() -> Result<T, E>>(
+    policy: &RetryPolicy,
+    mut operation: F,
+) -> Result<T, E> {
+    let mut attempt = 0;
+
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                attempt += 1;
+                if attempt >= policy.max_attempts {
+                    return Err(err);
+                }
+                thread::sleep(policy.delay_for_attempt(attempt - 1));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay_ms: 1,
+            max_delay_ms: 5,
+            jitter: false,
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_and_caps() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 500,
+            jitter: false,
+        };
+
+        assert_eq!(policy.delay_for_attempt(0), Duration::from_millis(100));
+        assert_eq!(policy.delay_for_attempt(1), Duration::from_millis(200));
+        assert_eq!(policy.delay_for_attempt(2), Duration::from_millis(400));
+        assert_eq!(policy.delay_for_attempt(3), Duration::from_millis(500));
+        assert_eq!(policy.delay_for_attempt(16), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn jitter_keeps_at_least_half_the_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 500,
+            jitter: true,
+        };
+
+        for attempt in 0..8 {
+            let delay = policy.delay_for_attempt(attempt);
+            let full = policy
+                .base_delay_ms
+                .saturating_mul(1 << attempt)
+                .min(policy.max_delay_ms);
+            assert!(delay >= Duration::from_millis(full / 2));
+            assert!(delay <= Duration::from_millis(full));
+        }
+    }
+
+    #[test]
+    fn retry_blocking_succeeds_after_failures() {
+        let mut calls = 0;
+        let result: Result<u32, &str> = retry_blocking(&fast_policy(5), || {
+            calls += 1;
+            if calls < 3 {
+                Err("not yet")
+            } else {
+                Ok(7)
+            }
+        });
+
+        assert_eq!(result, Ok(7));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn retry_blocking_returns_last_error_at_max_attempts() {
+        let mut calls = 0;
+        let result: Result<u32, u32> = retry_blocking(&fast_policy(3), || {
+            calls += 1;
+            Err(calls)
+        });
+
+        assert_eq!(result, Err(3));
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn async_retry_succeeds_after_failures() {
+        let mut calls = 0;
+        let result: Result<u32, &str> = retry(&fast_policy(5), || {
+            calls += 1;
+            if calls < 2 {
+                Err("not yet")
+            } else {
+                Ok(42)
+            }
+        })
+        .await;
+
+        assert_eq!(result, Ok(42));
+        assert_eq!(calls, 2);
+    }
+}
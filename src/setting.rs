@@ -1,15 +1,23 @@
 pub mod filter;
 
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::Command;
 use std::sync::{Arc, RwLock};
+#[cfg(feature = "network-capture")]
 use std::time::Duration;
 use std::{fmt, fs};
 
 use config_file::{ConfigFileError, FromConfigFile};
-use serde::{Deserialize, Deserializer};
+use regex::Regex;
+#[cfg(feature = "network-capture")]
+use serde::Deserializer;
+use serde::{Deserialize, Serialize};
 use serde_json;
 use toml;
 
 use crate::process::Pid;
+use crate::retry::RetryPolicy;
 
 use filter::Filter;
 
@@ -19,6 +27,326 @@ pub static mut GLOBAL_CONFIG: Option<Arc<RwLock<DaemonConfig>>> = None;
 pub struct MonitorTarget {
     pub container_name: String,
     pub pid_list: Vec<Pid>,
+
+    #[serde(default)]
+    pub tree_mode: TreeMode,
+
+    // host-namespace analog of container targeting: collect every process
+    // whose comm or cmdline matches this regex, ignoring pid_list
+    #[serde(default)]
+    pub process_name_pattern: Option<String>,
+
+    // collect every process in the pid namespace this path points to, e.g.
+    // /proc/<pid>/ns/pid. matching goes by namespace inode rather than
+    // setns, so only processes visible in the sensor's own /proc are found
+    // and the sensor needs permission to read their ns links
+    #[serde(default)]
+    pub pid_namespace: Option<PathBuf>,
+
+    // safety cap on how many pids this target may contribute to a sample; a
+    // runaway container gets a deterministic subset and a truncation flag
+    #[serde(default)]
+    pub max_pids: Option<usize>,
+}
+
+impl MonitorTarget {
+    // human-readable one-liner for logs and validation output
+    pub fn describe(&self) -> String {
+        match (&self.process_name_pattern, &self.pid_namespace) {
+            (Some(pattern), _) => format!(
+                "target '{}' matching /{}/, tree mode {:?}",
+                self.container_name, pattern, self.tree_mode
+            ),
+            (None, Some(ns_path)) => format!(
+                "target '{}' in pid namespace {}, tree mode {:?}",
+                self.container_name,
+                ns_path.display(),
+                self.tree_mode
+            ),
+            (None, None) => format!(
+                "target '{}' with {} pids, tree mode {:?}",
+                self.container_name,
+                self.pid_list.len(),
+                self.tree_mode
+            ),
+        }
+    }
+}
+
+impl fmt::Display for MonitorTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeMode {
+    // collect every process in the tree
+    Full,
+    // only emit the listed roots, their descendants are summed into them
+    RootsOnly,
+}
+
+impl Default for TreeMode {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Default for PayloadCompression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+// how memory is aggregated across a process tree: summing rss overcounts
+// pages shared between processes, pss (proportional set size) divides them
+// fairly but reading smaps_rollup is noticeably costlier per process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryAccounting {
+    Sum,
+    Pss,
+}
+
+impl Default for MemoryAccounting {
+    fn default() -> Self {
+        Self::Sum
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputSinkKind {
+    Kafka,
+    File,
+    Stdout,
+}
+
+impl Default for OutputSinkKind {
+    fn default() -> Self {
+        Self::Kafka
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputShape {
+    Tree,
+    Flat,
+    // influxdb line protocol, one measurement per process; never compressed
+    // so telegraf can ingest the chunks as-is
+    Influx,
+}
+
+impl Default for OutputShape {
+    fn default() -> Self {
+        Self::Tree
+    }
+}
+
+// what an unset "${VAR}" reference does at config load: keep the literal
+// text (with a warning) or refuse the config outright
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvVarPolicy {
+    Keep,
+    Error,
+}
+
+impl Default for EnvVarPolicy {
+    fn default() -> Self {
+        Self::Keep
+    }
+}
+
+// how taskstats are queried: thread walks every tid with one netlink round
+// trip each, process issues a single TGID-level query per process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskstatsGranularity {
+    Thread,
+    Process,
+}
+
+impl Default for TaskstatsGranularity {
+    fn default() -> Self {
+        Self::Thread
+    }
+}
+
+// how timestamps serialize: raw epoch numbers, or rfc3339 strings for
+// human-readable logs and tools that expect string timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    Unix,
+    Rfc3339,
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        Self::Unix
+    }
+}
+
+// which flavor of the cpu/io/network counters a sample carries: the raw
+// lifetime cumulatives, the per-interval deltas, or both side by side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldMode {
+    Cumulative,
+    Delta,
+    Both,
+}
+
+impl Default for FieldMode {
+    fn default() -> Self {
+        Self::Cumulative
+    }
+}
+
+// how much of a container a sample carries: every process object, or just
+// the per-container sums for cost-sensitive high-density hosts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerDetail {
+    Full,
+    Aggregate,
+}
+
+impl Default for ContainerDetail {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+// one cidr range in the connection include/exclude filters, parsed once at
+// config load like the normalization regexes
+#[derive(Debug, Clone, Deserialize)]
+pub struct CidrRange {
+    cidr: String,
+
+    #[serde(skip)]
+    network: Option<(IpAddr, u32)>,
+}
+
+impl CidrRange {
+    fn compile(&mut self) -> Result<(), ConfigError> {
+        let (addr, prefix_len) = self
+            .cidr
+            .split_once('/')
+            .ok_or_else(|| ConfigError::InvalidCidr(self.cidr.clone()))?;
+
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| ConfigError::InvalidCidr(self.cidr.clone()))?;
+        let prefix_len: u32 = prefix_len
+            .parse()
+            .map_err(|_| ConfigError::InvalidCidr(self.cidr.clone()))?;
+
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_prefix_len {
+            return Err(ConfigError::InvalidCidr(self.cidr.clone()));
+        }
+
+        self.network = Some((addr, prefix_len));
+        Ok(())
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        match self.network {
+            Some((network, prefix_len)) => ip_in_cidr(addr, &network, prefix_len),
+            None => false,
+        }
+    }
+}
+
+fn ip_in_cidr(addr: &IpAddr, network: &IpAddr, prefix_len: u32) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => {
+            if prefix_len == 0 {
+                return true;
+            }
+            let mask = u32::MAX << (32 - prefix_len);
+            (u32::from(*addr) & mask) == (u32::from(*network) & mask)
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) => {
+            if prefix_len == 0 {
+                return true;
+            }
+            let mask = u128::MAX << (128 - prefix_len);
+            (u128::from(*addr) & mask) == (u128::from(*network) & mask)
+        }
+        // a v4 address never falls in a v6 range and vice versa
+        _ => false,
+    }
+}
+
+// one regex replacement in the command_normalization ruleset, applied in order
+#[derive(Debug, Deserialize)]
+pub struct CommandNormalizationRule {
+    pattern: String,
+    replacement: String,
+
+    // compiled once at config load, never re-parsed during collection
+    #[serde(skip)]
+    compiled: Option<Regex>,
+}
+
+impl CommandNormalizationRule {
+    fn compile(&mut self) -> Result<(), ConfigError> {
+        match Regex::new(&self.pattern) {
+            Ok(regex) => {
+                self.compiled = Some(regex);
+                Ok(())
+            }
+            Err(err) => Err(ConfigError::InvalidNormalizationPattern(format!("{}", err))),
+        }
+    }
+
+    fn apply(&self, command: &str) -> String {
+        match &self.compiled {
+            Some(regex) => regex
+                .replace_all(command, self.replacement.as_str())
+                .into_owned(),
+            None => command.to_string(),
+        }
+    }
+}
+
+// per-unit rounding granularity applied while serializing counters; lightly
+// quantized values compress far better downstream at negligible dashboard cost
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub struct Quantization {
+    // round DataCount values to the nearest multiple of this many bytes
+    #[serde(default)]
+    data_bytes: Option<u128>,
+
+    // round TimeCount values to the nearest multiple of this many nanoseconds
+    #[serde(default)]
+    time_nanos: Option<u128>,
+}
+
+impl Quantization {
+    pub fn get_data_bytes(&self) -> Option<u128> {
+        self.data_bytes
+    }
+    pub fn get_time_nanos(&self) -> Option<u128> {
+        self.time_nanos
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,19 +354,261 @@ pub struct DaemonConfig {
     name: String,
     cluster: String,
 
+    // how unset "${VAR}" references in the labels above are handled
+    #[serde(default)]
+    env_var_policy: EnvVarPolicy,
+
     old_kernel: bool,
+
+    // capture tuning only exists alongside the capture threads; a sensor
+    // built without them just ignores these keys in its config
+    #[cfg(feature = "network-capture")]
     capture_size_limit: usize,
 
+    #[cfg(feature = "network-capture")]
     #[serde(deserialize_with = "duration_to_nanosecs")]
     control_command_receive_timeout: Duration,
 
+    #[cfg(feature = "network-capture")]
     #[serde(deserialize_with = "duration_to_nanosecs")]
     capture_thread_receive_timeout: Duration,
 
     dev_flag: bool,
+
+    // log a per-sample diff (appeared/disappeared/changed processes) to
+    // stdout, for interactive debugging of what a sensor sees move
+    #[serde(default)]
+    log_sample_diff: bool,
+
     publish_msg_interval: u64,
     monitor_targets: Vec<MonitorTarget>,
     msg_chunk_size: Option<usize>,
+
+    #[serde(default)]
+    output_shape: OutputShape,
+
+    #[serde(default)]
+    emit_host_aggregate: bool,
+
+    // allow-list of env var keys captured from /proc/<pid>/environ, never all of them
+    #[serde(default)]
+    capture_env_keys: Vec<String>,
+
+    // cap on per-interface connection series, the excess collapses into an "other" bucket
+    #[serde(default)]
+    max_connection_series: Option<usize>,
+
+    // bound on the live capture table per interface, oldest entries evicted first
+    #[cfg(feature = "network-capture")]
+    #[serde(default)]
+    max_connection_entries: Option<usize>,
+
+    // redis channels to listen on for config updates, empty means the built-in default
+    #[serde(default)]
+    config_channels: Vec<String>,
+
+    // redis channel the sensor acks applied/rejected config updates on
+    #[serde(default)]
+    config_ack_channel: Option<String>,
+
+    // codec applied to the serialized payload before chunking
+    #[serde(default)]
+    payload_compression: PayloadCompression,
+
+    // when set, a failed capture init degrades to no network stats instead of aborting
+    #[serde(default)]
+    capture_optional: bool,
+
+    // gzip the per-sample dev output files
+    #[serde(default)]
+    dev_compress: bool,
+
+    // keep container entries whose pid list matched nothing
+    #[serde(default)]
+    serialize_empty_containers: bool,
+
+    // append every sample as one json line to this file, independent of dev mode
+    #[serde(default)]
+    ndjson_output: Option<String>,
+
+    // rotate the ndjson file once it grows past this many bytes
+    #[serde(default)]
+    ndjson_max_bytes: Option<u64>,
+
+    // also emit per-interval *_delta fields computed against the previous sample
+    #[serde(default)]
+    emit_deltas: bool,
+
+    // cumulative counters, interval deltas, or both; delta and both imply
+    // delta computation regardless of emit_deltas
+    #[serde(default)]
+    field_mode: FieldMode,
+
+    // full per-process objects, or only the summed stats per container
+    #[serde(default)]
+    container_detail: ContainerDetail,
+
+    // transport the monitoring loop publishes through; dev_flag still forces file
+    #[serde(default)]
+    output_sink: OutputSinkKind,
+
+    // also emit the nanosecond-precision unix_nanos timestamp on samples
+    #[serde(default)]
+    timestamp_nanos: bool,
+
+    // sum or pss memory accounting for tree aggregation
+    #[serde(default)]
+    memory_accounting: MemoryAccounting,
+
+    // parse taskstats versions newer than 11 as their known v11 prefix
+    #[serde(default)]
+    allow_unknown_taskstats_version: bool,
+
+    // include link-layer type and mtu from sysfs in interface raw stats
+    #[cfg(feature = "network-capture")]
+    #[serde(default)]
+    interface_link_info: bool,
+
+    // ordered regex replacements deriving normalized_command from command
+    #[serde(default)]
+    command_normalization: Vec<CommandNormalizationRule>,
+
+    // serialize pids and large counters as quoted strings so javascript
+    // consumers keep precision above 2^53
+    #[serde(default)]
+    large_numbers_as_strings: bool,
+
+    // how long shutdown waits for the output sink to flush, 5s when unset
+    #[serde(default)]
+    shutdown_flush_timeout_secs: Option<u64>,
+
+    // align sampling ticks to wall-clock interval boundaries so samples from
+    // different hosts land at comparable times
+    #[serde(default)]
+    align_to_clock: bool,
+
+    // depth cap for the process-tree walk, 64 when unset
+    #[serde(default)]
+    max_tree_depth: Option<usize>,
+
+    // tag each sample with the host's hostname and kernel boot id so
+    // consumers can attribute samples and detect reboots
+    #[serde(default)]
+    tag_host_identity: bool,
+
+    // embed the stat-semantics map (cumulative vs gauge, units) into every
+    // sample so consumers don't have to hardcode field meanings
+    #[serde(default)]
+    emit_stat_semantics: bool,
+
+    // reuse a container's resolved pid list for up to this many seconds
+    // while its cgroup is unchanged; unset disables the cache
+    #[serde(default)]
+    container_pid_cache_secs: Option<u64>,
+
+    // optional output rounding, off by default
+    #[serde(default)]
+    quantization: Quantization,
+
+    // how long the rate-limited error logger suppresses repeats of the
+    // same error signature, 30s when unset
+    #[serde(default)]
+    error_log_window_secs: Option<u64>,
+
+    // character cap for captured command/exec_path strings, cut with an
+    // ellipsis marker; unset means unbounded
+    #[serde(default)]
+    max_string_len: Option<usize>,
+
+    // how long an idle connection's stats persist before pruning; unset
+    // keeps the old behavior of retaining every unattributed entry
+    #[serde(default)]
+    connection_ttl_secs: Option<u64>,
+
+    // chunks that still fail to publish after the sink's retries are
+    // appended here with the failure reason; unset drops them with a log
+    #[serde(default)]
+    dead_letter_path: Option<String>,
+
+    // cap on how many monitor targets one tick collects; the rest are
+    // round-robined across the following ticks. unset samples every target
+    #[serde(default)]
+    max_targets_per_tick: Option<usize>,
+
+    #[serde(default)]
+    taskstats_granularity: TaskstatsGranularity,
+
+    // netlink socket receive buffer size; batched taskstats queries on
+    // many-thread hosts overflow the default and drop responses (enobufs)
+    #[serde(default)]
+    netlink_rcvbuf_bytes: Option<usize>,
+
+    // kernel threads (no exe link, kthreadd ancestry) are skipped unless
+    // this is on, in which case they carry a kernel_thread marker
+    #[serde(default)]
+    include_kernel_threads: bool,
+
+    // emit one final record for a process that disappeared since the
+    // previous sample, so last-value consumers can retire its gauges
+    #[serde(default)]
+    emit_tombstones: bool,
+
+    // remote-address ranges that get per-connection stats; an empty include
+    // list means everything, excludes always win
+    #[serde(default)]
+    connection_cidr_include: Vec<CidrRange>,
+
+    #[serde(default)]
+    connection_cidr_exclude: Vec<CidrRange>,
+
+    #[serde(default)]
+    timestamp_format: TimestampFormat,
+
+    // cap on how many bytes the hex dump of a raw netlink buffer shows in
+    // error output; unset falls back to a built-in default
+    #[serde(default)]
+    debug_dump_max_bytes: Option<usize>,
+
+    // statvfs the root mount of each container for disk_total/used/avail
+    #[serde(default)]
+    collect_disk_usage: bool,
+
+    // ask the runtime at config load whether each configured container is
+    // actually running, warning about unknown names; off by default so
+    // offline validation works without a docker daemon
+    #[serde(default)]
+    verify_containers_at_load: bool,
+
+    // bounds for best-effort enrichment lookups (e.g. reverse dns): how
+    // many may run at once, and how long one may take before its result
+    // is discarded
+    #[serde(default = "default_enrichment_max_concurrency")]
+    enrichment_max_concurrency: usize,
+
+    #[serde(default = "default_enrichment_timeout_ms")]
+    enrichment_timeout_ms: u64,
+
+    // resolve connection remotes to hostnames through the bounded executor
+    #[serde(default)]
+    enrich_remote_hostnames: bool,
+
+    // single-purpose sensors can turn a whole collection phase off
+    #[serde(default = "default_collect_phase")]
+    collect_network: bool,
+
+    #[serde(default = "default_collect_phase")]
+    collect_process: bool,
+
+    // per-subsystem retry/backoff policies
+    #[serde(default)]
+    kafka_retry: RetryPolicy,
+
+    #[serde(default)]
+    redis_retry: RetryPolicy,
+
+    #[serde(default)]
+    netlink_retry: RetryPolicy,
+
     filter: Filter,
 }
 
@@ -52,18 +622,24 @@ impl DaemonConfig {
     pub fn is_old_kernel(&self) -> bool {
         self.old_kernel
     }
+    #[cfg(feature = "network-capture")]
     pub fn get_capture_size_limit(&self) -> usize {
         self.capture_size_limit
     }
+    #[cfg(feature = "network-capture")]
     pub fn get_control_command_receive_timeout(&self) -> Duration {
         self.control_command_receive_timeout
     }
+    #[cfg(feature = "network-capture")]
     pub fn get_capture_thread_receive_timeout(&self) -> Duration {
         self.capture_thread_receive_timeout
     }
     pub fn get_dev_flag(&self) -> bool {
         self.dev_flag
     }
+    pub fn get_log_sample_diff(&self) -> bool {
+        self.log_sample_diff
+    }
     pub fn get_monitor_targets(&self) -> Vec<MonitorTarget> {
         self.monitor_targets.clone()
     }
@@ -76,16 +652,350 @@ impl DaemonConfig {
     pub fn get_message_chunk_size(&self) -> Option<usize> {
         self.msg_chunk_size
     }
+    pub fn get_output_shape(&self) -> OutputShape {
+        self.output_shape
+    }
+    pub fn get_emit_host_aggregate(&self) -> bool {
+        self.emit_host_aggregate
+    }
+    pub fn get_capture_env_keys(&self) -> Vec<String> {
+        self.capture_env_keys.clone()
+    }
+    pub fn get_max_connection_series(&self) -> Option<usize> {
+        self.max_connection_series
+    }
+    #[cfg(feature = "network-capture")]
+    pub fn get_max_connection_entries(&self) -> Option<usize> {
+        self.max_connection_entries
+    }
+    pub fn get_config_channels(&self) -> Vec<String> {
+        self.config_channels.clone()
+    }
+    pub fn get_config_ack_channel(&self) -> Option<String> {
+        self.config_ack_channel.clone()
+    }
+    pub fn get_payload_compression(&self) -> PayloadCompression {
+        self.payload_compression
+    }
+    pub fn get_capture_optional(&self) -> bool {
+        self.capture_optional
+    }
+    pub fn get_dev_compress(&self) -> bool {
+        self.dev_compress
+    }
+    pub fn get_serialize_empty_containers(&self) -> bool {
+        self.serialize_empty_containers
+    }
+    pub fn get_ndjson_output(&self) -> Option<String> {
+        self.ndjson_output.clone()
+    }
+    pub fn get_ndjson_max_bytes(&self) -> Option<u64> {
+        self.ndjson_max_bytes
+    }
+    pub fn get_emit_deltas(&self) -> bool {
+        self.emit_deltas || self.field_mode != FieldMode::Cumulative
+    }
+    pub fn get_field_mode(&self) -> FieldMode {
+        self.field_mode
+    }
+    pub fn get_container_detail(&self) -> ContainerDetail {
+        self.container_detail
+    }
+    pub fn get_output_sink(&self) -> OutputSinkKind {
+        self.output_sink
+    }
+    pub fn get_timestamp_nanos(&self) -> bool {
+        self.timestamp_nanos
+    }
+    pub fn get_memory_accounting(&self) -> MemoryAccounting {
+        self.memory_accounting
+    }
+    pub fn get_allow_unknown_taskstats_version(&self) -> bool {
+        self.allow_unknown_taskstats_version
+    }
+    #[cfg(feature = "network-capture")]
+    pub fn get_interface_link_info(&self) -> bool {
+        self.interface_link_info
+    }
+    pub fn get_large_numbers_as_strings(&self) -> bool {
+        self.large_numbers_as_strings
+    }
+    pub fn get_shutdown_flush_timeout_secs(&self) -> Option<u64> {
+        self.shutdown_flush_timeout_secs
+    }
+    pub fn get_tag_host_identity(&self) -> bool {
+        self.tag_host_identity
+    }
+    pub fn get_emit_stat_semantics(&self) -> bool {
+        self.emit_stat_semantics
+    }
+    pub fn get_container_pid_cache_secs(&self) -> Option<u64> {
+        self.container_pid_cache_secs
+    }
+    pub fn get_quantization(&self) -> Quantization {
+        self.quantization
+    }
+    pub fn get_error_log_window_secs(&self) -> Option<u64> {
+        self.error_log_window_secs
+    }
+    pub fn get_max_string_len(&self) -> Option<usize> {
+        self.max_string_len
+    }
+    pub fn get_connection_ttl_secs(&self) -> Option<u64> {
+        self.connection_ttl_secs
+    }
+    pub fn get_dead_letter_path(&self) -> Option<String> {
+        self.dead_letter_path.clone()
+    }
+    pub fn get_max_targets_per_tick(&self) -> Option<usize> {
+        self.max_targets_per_tick
+    }
+    pub fn get_taskstats_granularity(&self) -> TaskstatsGranularity {
+        self.taskstats_granularity
+    }
+    pub fn get_netlink_rcvbuf_bytes(&self) -> Option<usize> {
+        self.netlink_rcvbuf_bytes
+    }
+    pub fn get_include_kernel_threads(&self) -> bool {
+        self.include_kernel_threads
+    }
+    pub fn get_emit_tombstones(&self) -> bool {
+        self.emit_tombstones
+    }
+    pub fn get_timestamp_format(&self) -> TimestampFormat {
+        self.timestamp_format
+    }
+    pub fn get_debug_dump_max_bytes(&self) -> Option<usize> {
+        self.debug_dump_max_bytes
+    }
+    pub fn get_collect_disk_usage(&self) -> bool {
+        self.collect_disk_usage
+    }
+    pub fn get_verify_containers_at_load(&self) -> bool {
+        self.verify_containers_at_load
+    }
+    pub fn get_enrichment_max_concurrency(&self) -> usize {
+        self.enrichment_max_concurrency
+    }
+    pub fn get_enrichment_timeout_ms(&self) -> u64 {
+        self.enrichment_timeout_ms
+    }
+    pub fn get_enrich_remote_hostnames(&self) -> bool {
+        self.enrich_remote_hostnames
+    }
+    pub fn get_align_to_clock(&self) -> bool {
+        self.align_to_clock
+    }
+    pub fn get_max_tree_depth(&self) -> Option<usize> {
+        self.max_tree_depth
+    }
+    pub fn get_collect_network(&self) -> bool {
+        self.collect_network
+    }
+    pub fn get_collect_process(&self) -> bool {
+        self.collect_process
+    }
+    // logical service name for grouping, None when no rules are configured
+    pub fn normalize_command(&self, command: &str) -> Option<String> {
+        if self.command_normalization.is_empty() {
+            return None;
+        }
+
+        let mut normalized = command.to_string();
+        for rule in &self.command_normalization {
+            normalized = rule.apply(&normalized);
+        }
+        Some(normalized)
+    }
+    fn compile_command_normalization(&mut self) -> Result<(), ConfigError> {
+        for rule in &mut self.command_normalization {
+            rule.compile()?;
+        }
+        Ok(())
+    }
+    fn compile_connection_cidrs(&mut self) -> Result<(), ConfigError> {
+        for range in &mut self.connection_cidr_include {
+            range.compile()?;
+        }
+        for range in &mut self.connection_cidr_exclude {
+            range.compile()?;
+        }
+        Ok(())
+    }
+    // whether a remote address gets per-connection detail: inside an include
+    // range (or no includes configured) and not in an exclude range
+    pub fn connection_address_included(&self, addr: &IpAddr) -> bool {
+        if self
+            .connection_cidr_exclude
+            .iter()
+            .any(|range| range.contains(addr))
+        {
+            return false;
+        }
+
+        self.connection_cidr_include.is_empty()
+            || self
+                .connection_cidr_include
+                .iter()
+                .any(|range| range.contains(addr))
+    }
+    pub fn get_kafka_retry(&self) -> RetryPolicy {
+        self.kafka_retry
+    }
+    pub fn get_redis_retry(&self) -> RetryPolicy {
+        self.redis_retry
+    }
+    pub fn get_netlink_retry(&self) -> RetryPolicy {
+        self.netlink_retry
+    }
+
+    // applied once at load, literal values pass through untouched
+    fn resolve_env_labels(&mut self) -> Result<(), ConfigError> {
+        self.name = interpolate_env(&self.name, self.env_var_policy)?;
+        self.cluster = interpolate_env(&self.cluster, self.env_var_policy)?;
+        Ok(())
+    }
 }
 
+#[cfg(feature = "network-capture")]
 fn duration_to_nanosecs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
     Ok(Duration::from_nanos(Deserialize::deserialize(
         deserializer,
     )?))
 }
 
+// resolve "${VAR}" references from the environment so the same config file
+// works across hosts; references may sit inside larger strings, e.g.
+// "prod-${HOSTNAME}". what happens on an unset var follows env_var_policy
+fn interpolate_env(value: &str, policy: EnvVarPolicy) -> Result<String, ConfigError> {
+    let reference = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+
+    let mut result = String::with_capacity(value.len());
+    let mut last_end = 0;
+    for captures in reference.captures_iter(value) {
+        let whole = captures.get(0).unwrap();
+        let var_name = &captures[1];
+
+        result.push_str(&value[last_end..whole.start()]);
+        match std::env::var(var_name) {
+            Ok(resolved) => result.push_str(&resolved),
+            Err(_) => match policy {
+                EnvVarPolicy::Keep => {
+                    println!(
+                        "warning: env var {} is unset, keeping {} as a literal",
+                        var_name,
+                        whole.as_str()
+                    );
+                    result.push_str(whole.as_str());
+                }
+                EnvVarPolicy::Error => {
+                    return Err(ConfigError::UnsetEnvVar(var_name.to_string()))
+                }
+            },
+        }
+        last_end = whole.end();
+    }
+    result.push_str(&value[last_end..]);
+
+    Ok(result)
+}
+
+// both collection phases stay on unless explicitly disabled
+fn default_collect_phase() -> bool {
+    true
+}
+
+fn default_enrichment_max_concurrency() -> usize {
+    8
+}
+
+fn default_enrichment_timeout_ms() -> u64 {
+    500
+}
+
+// warn below this, the collection itself can easily take longer
+const MIN_RECOMMENDED_PUBLISH_INTERVAL_SECS: u64 = 5;
+
+// a zero interval would make the monitoring loop spin
+fn validate_publish_interval(config: &DaemonConfig) -> Result<(), ConfigError> {
+    let interval = config.get_publish_msg_interval();
+
+    if interval == 0 {
+        return Err(ConfigError::InvalidPublishInterval(interval));
+    }
+    if interval < MIN_RECOMMENDED_PUBLISH_INTERVAL_SECS {
+        println!(
+            "warning: publish_msg_interval {}s is below the recommended minimum of {}s",
+            interval, MIN_RECOMMENDED_PUBLISH_INTERVAL_SECS
+        );
+    }
+
+    Ok(())
+}
+
+// first-run typo check: ask the runtime which containers are running and
+// warn about configured names it doesn't know. warn-only, so a stopped
+// container or an unreachable docker daemon never blocks startup
+fn warn_missing_containers(config: &DaemonConfig) {
+    let cmd_output = match Command::new("docker")
+        .args(["ps", "--format", "{{.Names}}"])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => {
+            println!("warning: can't query docker to verify configured container names");
+            return;
+        }
+    };
+
+    let running: Vec<String> = String::from_utf8_lossy(&cmd_output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect();
+
+    for monitor_target in config.get_monitor_targets() {
+        // pattern and namespace targets don't name a container, and "/"
+        // means the whole host
+        if monitor_target.process_name_pattern.is_some()
+            || monitor_target.pid_namespace.is_some()
+            || monitor_target.container_name == "/"
+        {
+            continue;
+        }
+
+        if !running
+            .iter()
+            .any(|name| *name == monitor_target.container_name)
+        {
+            println!(
+                "warning: container '{}' is not running, check the target name",
+                monitor_target.container_name
+            );
+        }
+    }
+}
+
 pub fn init_glob_conf(conf_path: &str) -> Result<(), ConfigError> {
-    let config = DaemonConfig::from_config_file(conf_path)?;
+    // distinguish the most common first-run failures (wrong path, bad
+    // permissions) from a file that exists but doesn't parse
+    let path = std::path::Path::new(conf_path);
+    if !path.exists() {
+        return Err(ConfigError::FileNotFound(path.to_path_buf()));
+    }
+    if let Err(err) = fs::File::open(path) {
+        return Err(ConfigError::Unreadable(path.to_path_buf(), err));
+    }
+
+    let mut config = DaemonConfig::from_config_file(conf_path)?;
+
+    config.resolve_env_labels()?;
+    config.compile_command_normalization()?;
+    config.compile_connection_cidrs()?;
+    validate_publish_interval(&config)?;
+
+    if config.get_verify_containers_at_load() {
+        warn_missing_containers(&config);
+    }
 
     unsafe {
         GLOBAL_CONFIG = Some(Arc::new(RwLock::new(config)));
@@ -101,10 +1011,25 @@ pub fn update_glob_conf(conf_path: String, conf_text: String) -> Result<(), Conf
         Ok(mut glob_conf) => {
             println!("{:?}", conf_text);
 
-            let config_in_json: DaemonConfig = serde_json::from_str(conf_text.as_ref()).unwrap();
+            // a malformed payload must come back as an error ack, not a panic
+            // in the config task
+            let mut config_in_json: DaemonConfig = match serde_json::from_str(conf_text.as_ref()) {
+                Ok(config_in_json) => config_in_json,
+                Err(_) => return Err(ConfigError::IncorrectConfig),
+            };
+            config_in_json.resolve_env_labels()?;
+            config_in_json.compile_command_normalization()?;
+            config_in_json.compile_connection_cidrs()?;
+            validate_publish_interval(&config_in_json)?;
+            if config_in_json.get_verify_containers_at_load() {
+                warn_missing_containers(&config_in_json);
+            }
             *glob_conf = config_in_json;
         
-            let config_in_toml: toml::Value = serde_json::from_str(conf_text.as_ref()).unwrap();
+            let config_in_toml: toml::Value = match serde_json::from_str(conf_text.as_ref()) {
+                Ok(config_in_toml) => config_in_toml,
+                Err(_) => return Err(ConfigError::IncorrectConfig),
+            };
             let _ = fs::write(conf_path, config_in_toml.to_string());
 
             Ok(())
@@ -122,11 +1047,39 @@ pub fn get_glob_conf() -> Result<Arc<RwLock<DaemonConfig>>, ConfigError> {
     }
 }
 
+// install one process-wide config for tests that exercise config-gated
+// paths (serialization filters, opt-in parse flags). the first caller wins
+// and later calls are no-ops, so every test sees the same settings
+#[cfg(test)]
+pub fn install_test_config() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        let mut config: DaemonConfig = toml::from_str(include_str!("../config.toml")).unwrap();
+
+        // the opt-in flags the cross-module tests exercise
+        config.allow_unknown_taskstats_version = true;
+        config.large_numbers_as_strings = true;
+        config.quantization = Quantization {
+            data_bytes: Some(1024),
+            time_nanos: None,
+        };
+
+        unsafe {
+            GLOBAL_CONFIG = Some(Arc::new(RwLock::new(config)));
+        }
+    });
+}
+
 pub fn has_unix_timestamp<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
     !glob_conf.get_filter().has_unix_timestamp()
 }
+pub fn has_unix_nanos<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf.get_timestamp_nanos()
+}
 pub fn has_irawstat_iname<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
@@ -265,6 +1218,47 @@ pub fn has_process_exec_path<T>(_: &T) -> bool {
     let glob_conf = binding.read().unwrap();
     !glob_conf.get_filter().get_process().has_exec_path()
 }
+pub fn has_process_supplementary_gids<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .has_supplementary_gids()
+}
+pub fn has_process_listening_ports<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .has_listening_ports()
+}
+pub fn has_process_socket_count<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf.get_filter().get_process().has_socket_count()
+}
+pub fn has_process_child_count<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf.get_filter().get_process().has_child_count()
+}
+pub fn has_process_process_uid<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf.get_filter().get_process().has_process_uid()
+}
+pub fn has_process_session_id<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf.get_filter().get_process().has_session_id()
+}
+pub fn has_process_tty_nr<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf.get_filter().get_process().has_tty_nr()
+}
 pub fn has_process_command<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
@@ -291,6 +1285,9 @@ pub fn has_process_stat_timestamp<T>(_: &T) -> bool {
 pub fn has_process_stat_total_system_cpu_time<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -300,6 +1297,9 @@ pub fn has_process_stat_total_system_cpu_time<T>(_: &T) -> bool {
 pub fn has_process_stat_total_user_cpu_time<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -309,6 +1309,9 @@ pub fn has_process_stat_total_user_cpu_time<T>(_: &T) -> bool {
 pub fn has_process_stat_total_cpu_time<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -342,9 +1345,57 @@ pub fn has_process_stat_total_swap<T>(_: &T) -> bool {
         .get_stat()
         .has_total_swap()
 }
+pub fn has_process_stat_peak_rss<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_stat()
+        .has_peak_rss()
+}
+pub fn has_process_stat_peak_vss<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_stat()
+        .has_peak_vss()
+}
+pub fn has_process_stat_minor_faults<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_stat()
+        .has_minor_faults()
+}
+pub fn has_process_stat_major_faults<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_stat()
+        .has_major_faults()
+}
+pub fn has_process_stat_delay_avgs<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_stat()
+        .has_delay_avgs()
+}
 pub fn has_process_stat_total_io_read<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -354,6 +1405,9 @@ pub fn has_process_stat_total_io_read<T>(_: &T) -> bool {
 pub fn has_process_stat_total_io_write<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -363,6 +1417,9 @@ pub fn has_process_stat_total_io_write<T>(_: &T) -> bool {
 pub fn has_process_stat_total_block_io_read<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -372,6 +1429,9 @@ pub fn has_process_stat_total_block_io_read<T>(_: &T) -> bool {
 pub fn has_process_stat_total_block_io_write<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -402,6 +1462,9 @@ pub fn has_process_netstat_pack_recv<T>(_: &T) -> bool {
 pub fn has_process_netstat_total_data_sent<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -412,6 +1475,9 @@ pub fn has_process_netstat_total_data_sent<T>(_: &T) -> bool {
 pub fn has_process_netstat_total_data_recv<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
+    if glob_conf.get_field_mode() == FieldMode::Delta {
+        return true;
+    }
     !glob_conf
         .get_filter()
         .get_process()
@@ -529,6 +1595,18 @@ pub fn has_process_istat_connection_stats<T>(_: &T) -> bool {
         .has_connection_stats()
 }
 
+pub fn has_connection_stat_retransmits<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_stat()
+        .get_netstat()
+        .get_interface_stat()
+        .has_connection_retransmits()
+}
+
 pub fn has_thread_tid<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
@@ -561,6 +1639,16 @@ pub fn has_thread_real_pid<T>(_: &T) -> bool {
         .has_real_pid()
 }
 
+pub fn has_thread_is_group_leader<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_thread()
+        .has_is_group_leader()
+}
+
 pub fn has_thread_stat_timestamp<T>(_: &T) -> bool {
     let binding = get_glob_conf().unwrap();
     let glob_conf = binding.read().unwrap();
@@ -641,12 +1729,58 @@ pub fn has_thread_stat_total_block_io_write<T>(_: &T) -> bool {
         .get_stat()
         .has_total_block_io_write()
 }
+pub fn has_thread_stat_last_cpu<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_thread()
+        .get_stat()
+        .has_last_cpu()
+}
+pub fn has_thread_stat_cpu_affinity<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_thread()
+        .get_stat()
+        .has_cpu_affinity()
+}
+pub fn has_thread_stat_minor_faults<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_thread()
+        .get_stat()
+        .has_minor_faults()
+}
+pub fn has_thread_stat_major_faults<T>(_: &T) -> bool {
+    let binding = get_glob_conf().unwrap();
+    let glob_conf = binding.read().unwrap();
+    !glob_conf
+        .get_filter()
+        .get_process()
+        .get_thread()
+        .get_stat()
+        .has_major_faults()
+}
 
 #[derive(Debug)]
 pub enum ConfigError {
     IncorrectConfig,
     LoadConfigErr(ConfigFileError),
     UninitializedConfig,
+    InvalidPublishInterval(u64),
+    InvalidNormalizationPattern(String),
+    InvalidCidr(String),
+    FileNotFound(std::path::PathBuf),
+    Unreadable(std::path::PathBuf, std::io::Error),
+    UnsetEnvVar(String),
 }
 
 impl std::error::Error for ConfigError {}
@@ -659,6 +1793,31 @@ impl fmt::Display for ConfigError {
             }
             Self::UninitializedConfig => String::from("Uninitialized config"),
             Self::IncorrectConfig => String::from("Incorrect config!"),
+            Self::InvalidPublishInterval(interval) => String::from(format!(
+                "Invalid publish_msg_interval {}s, it must be at least 1 second",
+                interval
+            )),
+            Self::InvalidNormalizationPattern(err) => String::from(format!(
+                "Invalid command_normalization pattern: {}",
+                err
+            )),
+            Self::InvalidCidr(cidr) => String::from(format!(
+                "Invalid connection cidr range: {}",
+                cidr
+            )),
+            Self::FileNotFound(path) => String::from(format!(
+                "Config file {} does not exist",
+                path.display()
+            )),
+            Self::Unreadable(path, err) => String::from(format!(
+                "Config file {} can't be read: {}",
+                path.display(),
+                err
+            )),
+            Self::UnsetEnvVar(var_name) => String::from(format!(
+                "Env var {} is unset and env_var_policy is \"error\"",
+                var_name
+            )),
         };
 
         write!(f, "{}", result)
@@ -670,3 +1829,83 @@ impl From<ConfigFileError> for ConfigError {
         Self::LoadConfigErr(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolate_env_resolves_whole_and_embedded_references() {
+        std::env::set_var("VS_TEST_LABEL", "prod-7");
+
+        assert_eq!(
+            interpolate_env("${VS_TEST_LABEL}", EnvVarPolicy::Keep).unwrap(),
+            "prod-7"
+        );
+        assert_eq!(
+            interpolate_env("sensor-${VS_TEST_LABEL}-a", EnvVarPolicy::Keep).unwrap(),
+            "sensor-prod-7-a"
+        );
+        assert_eq!(
+            interpolate_env("${VS_TEST_LABEL}/${VS_TEST_LABEL}", EnvVarPolicy::Keep).unwrap(),
+            "prod-7/prod-7"
+        );
+        assert_eq!(
+            interpolate_env("no references", EnvVarPolicy::Keep).unwrap(),
+            "no references"
+        );
+    }
+
+    #[test]
+    fn interpolate_env_follows_the_unset_policy() {
+        std::env::remove_var("VS_TEST_UNSET");
+
+        // keep leaves the reference literal, including inside larger strings
+        assert_eq!(
+            interpolate_env("a-${VS_TEST_UNSET}-b", EnvVarPolicy::Keep).unwrap(),
+            "a-${VS_TEST_UNSET}-b"
+        );
+
+        assert!(matches!(
+            interpolate_env("a-${VS_TEST_UNSET}-b", EnvVarPolicy::Error),
+            Err(ConfigError::UnsetEnvVar(var)) if var == "VS_TEST_UNSET"
+        ));
+    }
+
+    #[test]
+    fn env_var_policy_defaults_to_keep() {
+        assert_eq!(EnvVarPolicy::default(), EnvVarPolicy::Keep);
+    }
+
+    #[test]
+    fn ip_in_cidr_masks_by_prefix() {
+        let addr: IpAddr = "10.1.2.3".parse().unwrap();
+        let network: IpAddr = "10.1.0.0".parse().unwrap();
+
+        assert!(ip_in_cidr(&addr, &network, 16));
+        assert!(!ip_in_cidr(&addr, &network, 24));
+        // prefix zero matches everything
+        assert!(ip_in_cidr(&addr, &"0.0.0.0".parse().unwrap(), 0));
+        // families never match each other
+        assert!(!ip_in_cidr(&addr, &"::".parse().unwrap(), 0));
+    }
+
+    #[test]
+    fn cidr_range_compiles_and_rejects_malformed_input() {
+        let mut range = CidrRange {
+            cidr: String::from("192.168.0.0/16"),
+            network: None,
+        };
+        range.compile().unwrap();
+        assert!(range.contains(&"192.168.44.5".parse().unwrap()));
+        assert!(!range.contains(&"10.0.0.1".parse().unwrap()));
+
+        for bad in ["192.168.0.0", "not-an-addr/8", "10.0.0.0/33"] {
+            let mut range = CidrRange {
+                cidr: String::from(bad),
+                network: None,
+            };
+            assert!(matches!(range.compile(), Err(ConfigError::InvalidCidr(_))));
+        }
+    }
+}
@@ -38,6 +38,9 @@ pub struct InterfaceStat {
     real_data_sent: bool,
     real_data_recv: bool,
     connection_stats: bool,
+
+    #[serde(default)]
+    connection_retransmits: bool,
 }
 
 impl InterfaceStat {
@@ -65,6 +68,9 @@ impl InterfaceStat {
     pub fn has_connection_stats(&self) -> bool {
         self.connection_stats
     }
+    pub fn has_connection_retransmits(&self) -> bool {
+        self.connection_retransmits
+    }
 }
 
 #[derive(Deserialize, Clone, Copy, Debug)]
@@ -113,11 +119,27 @@ pub struct ProcessStat {
     total_rss: bool,
     total_vss: bool,
     total_swap: bool,
+
+    #[serde(default)]
+    peak_rss: bool,
+
+    #[serde(default)]
+    peak_vss: bool,
+
     total_io_read: bool,
     total_io_write: bool,
     total_block_io_read: bool,
     total_block_io_write: bool,
 
+    #[serde(default)]
+    minor_faults: bool,
+
+    #[serde(default)]
+    major_faults: bool,
+
+    #[serde(default)]
+    delay_avgs: bool,
+
     netstat: NetworkStat,
 }
 
@@ -147,6 +169,12 @@ impl ProcessStat {
     pub fn has_total_swap(&self) -> bool {
         self.total_swap
     }
+    pub fn has_peak_rss(&self) -> bool {
+        self.peak_rss
+    }
+    pub fn has_peak_vss(&self) -> bool {
+        self.peak_vss
+    }
     pub fn has_total_io_read(&self) -> bool {
         self.total_io_read
     }
@@ -159,6 +187,15 @@ impl ProcessStat {
     pub fn has_total_block_io_write(&self) -> bool {
         self.total_block_io_write
     }
+    pub fn has_minor_faults(&self) -> bool {
+        self.minor_faults
+    }
+    pub fn has_major_faults(&self) -> bool {
+        self.major_faults
+    }
+    pub fn has_delay_avgs(&self) -> bool {
+        self.delay_avgs
+    }
 }
 
 #[derive(Deserialize, Clone, Copy, Debug)]
@@ -171,6 +208,18 @@ pub struct ThreadStat {
     total_io_write: bool,
     total_block_io_read: bool,
     total_block_io_write: bool,
+
+    #[serde(default)]
+    last_cpu: bool,
+
+    #[serde(default)]
+    cpu_affinity: bool,
+
+    #[serde(default)]
+    minor_faults: bool,
+
+    #[serde(default)]
+    major_faults: bool,
 }
 
 impl ThreadStat {
@@ -198,6 +247,18 @@ impl ThreadStat {
     pub fn has_total_block_io_write(&self) -> bool {
         self.total_block_io_write
     }
+    pub fn has_last_cpu(&self) -> bool {
+        self.last_cpu
+    }
+    pub fn has_cpu_affinity(&self) -> bool {
+        self.cpu_affinity
+    }
+    pub fn has_minor_faults(&self) -> bool {
+        self.minor_faults
+    }
+    pub fn has_major_faults(&self) -> bool {
+        self.major_faults
+    }
 }
 
 #[derive(Deserialize, Clone, Copy, Debug)]
@@ -207,6 +268,9 @@ pub struct Thread {
     real_tid: bool,
     real_pid: bool,
 
+    #[serde(default)]
+    is_group_leader: bool,
+
     stat: ThreadStat,
 }
 
@@ -227,6 +291,9 @@ impl Thread {
     pub fn has_real_pid(&self) -> bool {
         self.real_pid
     }
+    pub fn has_is_group_leader(&self) -> bool {
+        self.is_group_leader
+    }
 }
 
 #[derive(Deserialize, Clone, Copy, Debug)]
@@ -256,6 +323,27 @@ pub struct Process {
     command: bool,
     child_real_pid_list: bool,
 
+    #[serde(default)]
+    supplementary_gids: bool,
+
+    #[serde(default)]
+    listening_ports: bool,
+
+    #[serde(default)]
+    socket_count: bool,
+
+    #[serde(default)]
+    child_count: bool,
+
+    #[serde(default)]
+    process_uid: bool,
+
+    #[serde(default)]
+    session_id: bool,
+
+    #[serde(default)]
+    tty_nr: bool,
+
     stat: ProcessStat,
     thread: Thread
 }
@@ -333,6 +421,27 @@ impl Process {
     pub fn has_child_real_pid_list(&self) -> bool {
         self.child_real_pid_list
     }
+    pub fn has_supplementary_gids(&self) -> bool {
+        self.supplementary_gids
+    }
+    pub fn has_listening_ports(&self) -> bool {
+        self.listening_ports
+    }
+    pub fn has_child_count(&self) -> bool {
+        self.child_count
+    }
+    pub fn has_socket_count(&self) -> bool {
+        self.socket_count
+    }
+    pub fn has_process_uid(&self) -> bool {
+        self.process_uid
+    }
+    pub fn has_session_id(&self) -> bool {
+        self.session_id
+    }
+    pub fn has_tty_nr(&self) -> bool {
+        self.tty_nr
+    }
 
     pub fn get_stat(&self) -> &ProcessStat {
         &self.stat
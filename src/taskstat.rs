@@ -1,9 +1,11 @@
 use std::convert::{TryFrom, TryInto};
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fmt, mem, slice};
 
-use crate::common::{Count, DataCount, Gid, TimeCount, Timestamp, Uid};
+use crate::common::{self, Count, DataCount, Gid, TimeCount, Timestamp, Uid};
+use crate::setting;
 use crate::netlink::generic::{GenericError, GenericNetlinkConnection};
 use crate::netlink::generic::{GenericNetlinkControlMessage, GenericNetlinkControlMessageCommand};
 use crate::netlink::generic::{
@@ -117,9 +119,14 @@ impl TaskStatsRawV8 {
     }
 
     pub fn command_str(&self) -> String {
-        std::str::from_utf8(&self.command_str)
-            .unwrap()
-            .to_string()
+        // comm is a fixed 32-byte buffer, cut at the first nul and replace
+        // invalid utf-8 instead of crashing collection on arbitrary bytes
+        let len = self
+            .command_str
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(self.command_str.len());
+        String::from_utf8_lossy(&self.command_str[..len]).into_owned()
     }
 
     pub fn to_taskstats(&self) -> TaskStats {
@@ -310,9 +317,14 @@ impl TaskStatsRawV9 {
     }
 
     pub fn get_command_str(&self) -> String {
-        std::str::from_utf8(&self.command_str)
-            .unwrap()
-            .to_string()
+        // comm is a fixed 32-byte buffer, cut at the first nul and replace
+        // invalid utf-8 instead of crashing collection on arbitrary bytes
+        let len = self
+            .command_str
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(self.command_str.len());
+        String::from_utf8_lossy(&self.command_str[..len]).into_owned()
     }
 
     pub fn to_taskstats(&self) -> TaskStats {
@@ -507,9 +519,14 @@ impl TaskStatsRawV10 {
     }
 
     pub fn command_str(&self) -> String {
-        std::str::from_utf8(&self.command_str)
-            .unwrap()
-            .to_string()
+        // comm is a fixed 32-byte buffer, cut at the first nul and replace
+        // invalid utf-8 instead of crashing collection on arbitrary bytes
+        let len = self
+            .command_str
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(self.command_str.len());
+        String::from_utf8_lossy(&self.command_str[..len]).into_owned()
     }
 
     pub fn to_taskstats(&self) -> TaskStats {
@@ -707,9 +724,14 @@ impl TaskStatsRawV11 {
     }
 
     pub fn command_str(&self) -> String {
-        std::str::from_utf8(&self.command_str)
-            .unwrap()
-            .to_string()
+        // comm is a fixed 32-byte buffer, cut at the first nul and replace
+        // invalid utf-8 instead of crashing collection on arbitrary bytes
+        let len = self
+            .command_str
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(self.command_str.len());
+        String::from_utf8_lossy(&self.command_str[..len]).into_owned()
     }
 
     pub fn to_taskstats(&self) -> TaskStats {
@@ -799,6 +821,35 @@ impl TaskStatsRawV11 {
     }
 }
 
+impl TaskStatsRawV11 {
+    // parse the known v11 prefix of a newer append-only struct version,
+    // skipping the version check
+    pub fn from_byte_array_prefix(buf: &[u8]) -> Result<Self, TaskStatsError> {
+        if buf.len() < Self::LENGTH {
+            return Err(TaskStatsError::TaskStructErr(buf.to_vec()));
+        }
+
+        Ok(unsafe { *(buf as *const _ as *mut Self) })
+    }
+}
+
+// log the unknown version only once, not per queried thread
+static UNKNOWN_VERSION_LOGGED: AtomicBool = AtomicBool::new(false);
+
+// same for a version/length disagreement
+static VERSION_LENGTH_MISMATCH_LOGGED: AtomicBool = AtomicBool::new(false);
+
+// the version the kernel actually produced, recorded after the first
+// successful parse and surfaced on every sample; zero means none yet
+static DETECTED_TASKSTATS_VERSION: AtomicU16 = AtomicU16::new(0);
+
+pub fn detected_taskstats_version() -> Option<u16> {
+    match DETECTED_TASKSTATS_VERSION.load(Ordering::Relaxed) {
+        0 => None,
+        version => Some(version),
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum TaskStatsRaw {
     V8(TaskStatsRawV8),
@@ -817,17 +868,69 @@ impl TaskStatsRaw {
         }
     }
 
+    // the struct version whose exact size matches an attribute payload length
+    fn version_for_length(length: usize) -> Option<u16> {
+        match length {
+            TaskStatsRawV8::LENGTH => Some(TaskStatsRawV8::VERSION),
+            TaskStatsRawV9::LENGTH => Some(TaskStatsRawV9::VERSION),
+            TaskStatsRawV10::LENGTH => Some(TaskStatsRawV10::VERSION),
+            TaskStatsRawV11::LENGTH => Some(TaskStatsRawV11::VERSION),
+            _ => None,
+        }
+    }
+
     pub fn from_byte_array(buf: &[u8]) -> Result<Self, TaskStatsError> {
         // get version
         let version = u16::from_ne_bytes(buf[0..2].try_into().unwrap());
 
-        match version {
+        // cross-check against the attribute length: when the payload is the
+        // exact size of a different struct version the length wins, the
+        // version field has been seen to lag behind kernel padding changes
+        if let Some(length_version) = Self::version_for_length(buf.len()) {
+            if length_version != version {
+                if !VERSION_LENGTH_MISMATCH_LOGGED.swap(true, Ordering::Relaxed) {
+                    println!(
+                        "warning: taskstats version {} but payload sized for version {}, using the length",
+                        version, length_version
+                    );
+                }
+
+                // restamp the version field and reparse; the payload is
+                // already laid out for the length-detected version
+                let mut corrected = buf.to_vec();
+                corrected[0..2].copy_from_slice(&length_version.to_ne_bytes());
+                return Self::from_byte_array(&corrected);
+            }
+        }
+
+        let result = match version {
             8 => Ok(Self::V8(TaskStatsRawV8::from_byte_array(buf)?)),
             9 => Ok(Self::V9(TaskStatsRawV9::from_byte_array(buf)?)),
             10 => Ok(Self::V10(TaskStatsRawV10::from_byte_array(buf)?)),
             11 => Ok(Self::V11(TaskStatsRawV11::from_byte_array(buf)?)),
+            // the kernel only appends fields to the struct, so with the opt-in
+            // flag a newer version parses as its known v11 prefix
+            _ if version > TaskStatsRawV11::VERSION
+                && setting::get_glob_conf()
+                    .map(|conf| conf.read().unwrap().get_allow_unknown_taskstats_version())
+                    .unwrap_or(false) =>
+            {
+                if !UNKNOWN_VERSION_LOGGED.swap(true, Ordering::Relaxed) {
+                    println!(
+                        "warning: unknown taskstats version {}, parsing the v11 prefix",
+                        version
+                    );
+                }
+                Ok(Self::V11(TaskStatsRawV11::from_byte_array_prefix(buf)?))
+            }
             _ => Err(TaskStatsError::UnsupportedTaskstatsVersion(version)),
+        };
+
+        if result.is_ok() {
+            DETECTED_TASKSTATS_VERSION.store(version, Ordering::Relaxed);
         }
+
+        result
     }
 
     pub fn to_taskstats(&self) -> TaskStats {
@@ -1136,17 +1239,48 @@ impl Into<GenericNetlinkMessageAttribute> for TaskStatsResultAttribute {
             Self::Stats(stats) => {
                 GenericNetlinkMessageAttribute::new(self.get_type().into(), stats.to_byte_array())
             }
-            Self::AggrPid(_aggregate_pid) => {
-                unimplemented!()
-            }
-            Self::AggrTGid(_aggregate_tgid) => {
-                unimplemented!()
-            }
+            Self::AggrPid(aggregate_pid) => GenericNetlinkMessageAttribute::new(
+                self.get_type().into(),
+                aggregate_payload(
+                    aggregate_pid.tid.into(),
+                    TaskStatsResultAttributeType::PID,
+                    &aggregate_pid.stats,
+                ),
+            ),
+            Self::AggrTGid(aggregate_tgid) => GenericNetlinkMessageAttribute::new(
+                self.get_type().into(),
+                aggregate_payload(
+                    aggregate_tgid.pid.into(),
+                    TaskStatsResultAttributeType::TGID,
+                    &aggregate_tgid.stats,
+                ),
+            ),
             Self::NULL => GenericNetlinkMessageAttribute::new(self.get_type().into(), [].to_vec()),
         }
     }
 }
 
+// nested payload of an aggregate attribute, the mirror image of the parser
+// below: an id attribute with the value at [4..8], then a stats attribute
+// whose payload starts at [12..]
+fn aggregate_payload(
+    id: u32,
+    id_type: TaskStatsResultAttributeType,
+    stats: &TaskStatsRaw,
+) -> Vec<u8> {
+    let stats_bytes = stats.to_byte_array();
+
+    let mut payload = Vec::with_capacity(12 + stats_bytes.len());
+    payload.extend_from_slice(&8u16.to_ne_bytes());
+    payload.extend_from_slice(&(id_type as u16).to_ne_bytes());
+    payload.extend_from_slice(&id.to_ne_bytes());
+    payload.extend_from_slice(&((4 + stats_bytes.len()) as u16).to_ne_bytes());
+    payload.extend_from_slice(&(TaskStatsResultAttributeType::Stats as u16).to_ne_bytes());
+    payload.extend_from_slice(&stats_bytes);
+
+    payload
+}
+
 impl TryFrom<TaskStatsAttribute> for TaskStatsResultAttribute {
     type Error = TaskStatsError;
 
@@ -1365,6 +1499,9 @@ impl TaskStatsConnection {
         }
     }
 
+    // TGID-level aggregate over the whole thread group. not used by the stat
+    // collection path, which sums per-thread taskstats instead — mixing the two
+    // would count every thread twice
     pub fn get_process_taskstats(&self, real_pid: Pid) -> Result<TaskStats, TaskStatsError> {
         let mut taskstats_message =
             TaskStatsMessage::new(self.taskstats_family_id, TaskStatsCommand::GET);
@@ -1394,6 +1531,23 @@ impl TaskStatsConnection {
     }
 }
 
+// the slice of TaskStatsConnection the stat collection path depends on, so
+// tests can substitute a stub for the netlink transport
+pub trait TaskStatsSource {
+    fn thread_taskstats(&self, real_tid: Tid) -> Result<TaskStats, TaskStatsError>;
+    fn process_taskstats(&self, real_pid: Pid) -> Result<TaskStats, TaskStatsError>;
+}
+
+impl TaskStatsSource for TaskStatsConnection {
+    fn thread_taskstats(&self, real_tid: Tid) -> Result<TaskStats, TaskStatsError> {
+        self.get_thread_taskstats(real_tid)
+    }
+
+    fn process_taskstats(&self, real_pid: Pid) -> Result<TaskStats, TaskStatsError> {
+        self.get_process_taskstats(real_pid)
+    }
+}
+
 #[derive(Debug)]
 pub enum TaskStatsError {
     GenericError(GenericError),
@@ -1409,6 +1563,9 @@ pub enum TaskStatsError {
     WrongResultType(TaskStatsResultAttribute),
 }
 
+// enough context to identify the failing struct version without flooding logs
+const DEFAULT_DEBUG_DUMP_MAX_BYTES: usize = 256;
+
 impl Error for TaskStatsError {}
 
 impl fmt::Display for TaskStatsError {
@@ -1431,7 +1588,16 @@ impl fmt::Display for TaskStatsError {
                 taskstats_attr_type
             )),
             Self::TaskStructErr(buf) => {
-                String::from(format!("Raw taskstats struct error: {:?}", buf))
+                let max_bytes = setting::get_glob_conf()
+                    .map(|conf| conf.read().unwrap().get_debug_dump_max_bytes())
+                    .unwrap_or(None)
+                    .unwrap_or(DEFAULT_DEBUG_DUMP_MAX_BYTES);
+
+                String::from(format!(
+                    "Raw taskstats struct error, {} bytes:\n{}",
+                    buf.len(),
+                    common::hexdump(buf, max_bytes)
+                ))
             }
             Self::WrongTid(tid) => String::from(format!("Wrong tid from result: {:?}", tid)),
             Self::WrongPid(pid) => String::from(format!("Wrong pid from result: {:?}", pid)),
@@ -1450,3 +1616,96 @@ impl From<GenericError> for TaskStatsError {
         Self::GenericError(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // from_byte_array records the detected version in a process-wide static,
+    // so tests that parse buffers serialize around this lock
+    static DETECTED_VERSION_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn version_for_length_matches_the_struct_sizes() {
+        assert_eq!(
+            TaskStatsRaw::version_for_length(TaskStatsRawV8::LENGTH),
+            Some(8)
+        );
+        assert_eq!(
+            TaskStatsRaw::version_for_length(TaskStatsRawV11::LENGTH),
+            Some(11)
+        );
+        assert_eq!(TaskStatsRaw::version_for_length(1), None);
+    }
+
+    #[test]
+    fn length_overrides_a_lagging_version_field_and_is_recorded() {
+        let _guard = DETECTED_VERSION_LOCK.lock().unwrap();
+
+        // a zeroed buffer claims version 0, but its size identifies v10;
+        // the length wins and the detected version is published
+        let buf = vec![0u8; TaskStatsRawV10::LENGTH];
+
+        let parsed = TaskStatsRaw::from_byte_array(&buf).unwrap();
+        assert!(matches!(parsed, TaskStatsRaw::V10(_)));
+        assert_eq!(detected_taskstats_version(), Some(10));
+    }
+
+    #[test]
+    fn command_str_is_nul_trimmed_and_utf8_lossy() {
+        let mut raw: TaskStatsRawV11 = unsafe { mem::zeroed() };
+        raw.command_str[0] = b'n';
+        raw.command_str[1] = 0xff; // invalid utf-8 inside the comm
+        raw.command_str[2] = b'x';
+        raw.command_str[3] = 0;
+        raw.command_str[4] = b'!'; // bytes past the nul must not leak through
+
+        // invalid bytes are replaced instead of panicking, and the string
+        // stops at the first nul
+        assert_eq!(raw.command_str(), "n\u{fffd}x");
+    }
+
+    #[test]
+    fn aggr_pid_attribute_round_trips_through_its_wire_form() {
+        let _guard = DETECTED_VERSION_LOCK.lock().unwrap();
+
+        // a v11 stats payload with a distinctive byte so the round trip is
+        // checked against real content, not just zeroes
+        let mut buf = vec![0u8; TaskStatsRawV11::LENGTH];
+        buf[0..2].copy_from_slice(&11u16.to_ne_bytes());
+        buf[100] = 0xab;
+        let stats = TaskStatsRaw::from_byte_array(&buf).unwrap();
+
+        let attribute = TaskStatsResultAttribute::AggrPid(
+            TaskStatsResultAttributeAggregatePid::new(Tid::new(4321), stats),
+        );
+
+        // serialize to the generic netlink form and parse it back
+        let generic: GenericNetlinkMessageAttribute = attribute.into();
+        let parsed = TaskStatsResultAttribute::try_from(TaskStatsAttribute::from(generic)).unwrap();
+
+        match parsed {
+            TaskStatsResultAttribute::AggrPid(aggregate) => {
+                assert_eq!(aggregate.tid, Tid::new(4321));
+                assert_eq!(aggregate.stats.to_byte_array(), buf);
+            }
+            other => panic!("expected AggrPid, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_newer_version_parses_as_its_v11_prefix_when_allowed() {
+        setting::install_test_config();
+        let _guard = DETECTED_VERSION_LOCK.lock().unwrap();
+
+        // a v12 payload: the v11 layout plus appended fields we don't know,
+        // sized so it can't be mistaken for any exact known struct
+        let mut buf = vec![0u8; TaskStatsRawV11::LENGTH + 3];
+        buf[0..2].copy_from_slice(&12u16.to_ne_bytes());
+
+        let parsed = TaskStatsRaw::from_byte_array(&buf).unwrap();
+        assert!(matches!(parsed, TaskStatsRaw::V11(_)));
+        assert_eq!(detected_taskstats_version(), Some(12));
+    }
+}
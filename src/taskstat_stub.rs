@@ -0,0 +1,108 @@
+// stub taskstats backend for non-linux targets; taskstats is a linux netlink
+// interface, so off-linux every call compiles but fails with Unsupported
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::common::{Count, DataCount, Gid, TimeCount, Timestamp, Uid};
+use crate::process::{Pid, Tid};
+
+#[derive(Debug, Clone)]
+#[allow(unused)]
+pub struct TaskStats {
+    pub command_str: String,
+    pub pid: Pid,
+    pub uid: Uid,
+    pub gid: Gid,
+    pub parent_pid: Pid,
+    pub nice: isize,
+    pub flags: usize,
+    pub exitcode: usize,
+    pub timestamp: Timestamp,
+
+    pub begin_time: SystemTime,
+    pub elapsed_time: TimeCount,
+    pub scheduling_discipline: u8,
+
+    pub user_cpu_time: TimeCount,
+    pub system_cpu_time: TimeCount,
+
+    pub accumulated_rss: DataCount,
+    pub accumulated_vss: DataCount,
+
+    pub high_water_rss: DataCount,
+    pub high_water_vss: DataCount,
+
+    pub io_read: DataCount,
+    pub io_write: DataCount,
+
+    pub read_syscall_count: Count,
+    pub write_syscall_count: Count,
+
+    pub block_io_read: DataCount,
+    pub block_io_write: DataCount,
+    pub cancelled_block_io_write: DataCount,
+
+    pub cpu_delay_count: Count,
+    pub cpu_delay_total: TimeCount,
+
+    pub minor_fault_count: Count,
+    pub major_fault_count: Count,
+
+    pub free_pages_delay_count: Count,
+    pub free_pages_delay_total: TimeCount,
+
+    pub thrashing_delay_count: Count,
+    pub thrashing_delay_total: TimeCount,
+
+    pub block_io_delay_count: Count,
+    pub block_io_delay_total: TimeCount,
+
+    pub swapin_delay_count: Count,
+    pub swapin_delay_total: TimeCount,
+
+    pub memory_compact_delay_count: Count,
+    pub memory_compact_delay_total: TimeCount,
+
+    pub voluntary_context_switches: Count,
+    pub nonvoluntary_context_switches: Count,
+
+    pub cpu_runtime_real_total: TimeCount,
+    pub cpu_runtime_virtual_total: TimeCount,
+}
+
+#[derive(Debug)]
+pub struct TaskStatsConnection {}
+
+impl TaskStatsConnection {
+    pub fn new() -> Result<Self, TaskStatsError> {
+        Err(TaskStatsError::Unsupported)
+    }
+
+    pub fn get_thread_taskstats(&self, _real_tid: Tid) -> Result<TaskStats, TaskStatsError> {
+        Err(TaskStatsError::Unsupported)
+    }
+
+    pub fn get_process_taskstats(&self, _real_pid: Pid) -> Result<TaskStats, TaskStatsError> {
+        Err(TaskStatsError::Unsupported)
+    }
+}
+
+#[derive(Debug)]
+pub enum TaskStatsError {
+    Unsupported,
+}
+
+impl Error for TaskStatsError {}
+
+impl fmt::Display for TaskStatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self {
+            Self::Unsupported => String::from(format!(
+                "Taskstats is only available on linux, this platform is unsupported"
+            )),
+        };
+
+        write!(f, "{}", result)
+    }
+}